@@ -0,0 +1,407 @@
+use crate::bitboard;
+use crate::board::*;
+use crate::rules;
+use crate::rules::BlockType;
+use crate::settings::{GameSettings, PlacementMode};
+use crate::util::*;
+use crate::zobrist;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const WIN_SCORE: i64 = 1_000_000_000_000;
+pub const MAX_BRANCHING: usize = 12; // Only the most promising moves are searched
+pub const MAX_DEPTH: i32 = 6;
+pub const TRANSPOSITION_TABLE_SIZE_LOG2: u32 = 18;
+
+// Name and search depth
+pub const DIFFICULTIES: [(&str, i32); 3] = [("easy", 1), ("medium", 2), ("hard", 4)];
+pub const DEFAULT_DEPTH: i32 = 2;
+pub const SWAP_DEPTH: i32 = 2;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AlphaBetaConfig {
+    pub depth: i32,
+    pub use_symmetries: bool, // Rotated, reflected or shifted positions share their transposition table entries
+}
+
+// Value of a line with `count` symbols of a single player, grows fast so that threats come first
+pub fn get_line_weight(count: usize) -> i64 {
+    return 8i64.pow(count as u32) - 1;
+}
+
+// Board and per-line symbol counts, updated as moves are tried and taken back
+pub struct Search<'a> {
+    pub masks: &'a bitboard::LineMasks,
+    pub neighbours: Vec<Vec<usize>>,
+    pub settings: &'a GameSettings,
+    pub board: Board,
+    pub bits: bitboard::BitBoard,
+    pub counts: Vec<[u8; 4]>, // Symbols of each player in each line
+    pub me: u8, // Player the scores are computed for
+    pub score: i64,
+    pub hasher: zobrist::Hasher,
+    pub transpositions: zobrist::TranspositionTable, // Only used by the alpha-beta search, tiny otherwise
+}
+
+pub fn new_search<'a>(board: &Board, me: u8, masks: &'a bitboard::LineMasks, settings: &'a GameSettings) -> Search<'a> {
+    let mut search = Search {
+        masks,
+        neighbours: get_neighbour_table(&board.config),
+        settings,
+        board: new_board(&board.config),
+        bits: bitboard::new_bitboard(),
+        counts: vec![[0; 4]; masks.masks.len()],
+        me,
+        score: 0,
+        hasher: zobrist::new_hasher(&new_board(&board.config), settings, false),
+        transpositions: zobrist::new_transposition_table(0),
+    };
+
+    for (id, block) in board.blocks.iter().enumerate() {
+        match block {
+            BlockType::Player(player) => { make_move(id, *player, &mut search); },
+            BlockType::None => {},
+        }
+    }
+
+    return search;
+}
+
+// Positive when the line only helps `me`, negative when it only helps one opponent
+fn get_line_value(counts: &[u8; 4], me: u8) -> i64 {
+    let mut owner = None;
+    for (player, count) in counts.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        if owner.is_some() {
+            return 0; // Blocked
+        }
+        owner = Some(player);
+    }
+
+    match owner {
+        Some(player) => {
+            let value = get_line_weight(counts[player] as usize);
+            return if player == me as usize { value } else { -value };
+        },
+        None => return 0,
+    }
+}
+
+// Returns true if the move completes a line
+pub fn make_move(id: usize, player: u8, search: &mut Search) -> bool {
+    let mut won = false;
+    for line in &search.masks.lines_of_block[id] {
+        let before = get_line_value(&search.counts[*line], search.me);
+        search.counts[*line][player as usize] += 1;
+        search.score += get_line_value(&search.counts[*line], search.me) - before;
+
+        if search.counts[*line][player as usize] as i32 == search.settings.count_to_win {
+            won = true;
+        }
+    }
+
+    search.board.blocks[id] = BlockType::Player(player);
+    bitboard::make_move(id, player, &mut search.bits);
+    zobrist::toggle_block(id, player, &mut search.hasher);
+    return won;
+}
+
+pub fn unmake_move(id: usize, player: u8, search: &mut Search) {
+    for line in &search.masks.lines_of_block[id] {
+        let before = get_line_value(&search.counts[*line], search.me);
+        search.counts[*line][player as usize] -= 1;
+        search.score += get_line_value(&search.counts[*line], search.me) - before;
+    }
+
+    search.board.blocks[id] = BlockType::None;
+    bitboard::unmake_move(id, player, &mut search.bits);
+    zobrist::toggle_block(id, player, &mut search.hasher);
+}
+
+// Empty blocks where a symbol can land. Without gravity, only the ones next to a symbol (any block on an empty cube).
+pub fn get_candidate_moves(search: &Search) -> Vec<usize> {
+    let occupied = &search.bits.occupied;
+    let config = &search.board.config;
+    let empty = (0..search.board.blocks.len()).filter(|id| !bitboard::has_bit(*id, occupied));
+
+    match search.settings.placement {
+        PlacementMode::Gravity(_) => {
+            return empty.filter(|id| {
+                let pos = id_to_pos(*id as i32, config);
+                return rules::get_landing_pos(&pos, &search.board, search.settings) == Some(pos);
+            }).collect();
+        },
+        PlacementMode::Free => {
+            let touching: Vec<usize> = empty.clone().filter(|id| {
+                return search.neighbours[*id].iter().any(|other| bitboard::has_bit(*other, occupied));
+            }).collect();
+
+            if touching.is_empty() {
+                return empty.collect();
+            }
+            return touching;
+        },
+    }
+}
+
+// How much a move helps `player`, by extending its lines and blocking the others
+fn get_move_priority(id: usize, player: u8, search: &Search) -> i64 {
+    let mut priority = 0;
+    for line in &search.masks.lines_of_block[id] {
+        let counts = &search.counts[*line];
+        let owners: Vec<usize> = (0..4).filter(|p| counts[*p] > 0).collect();
+
+        match owners.len() {
+            0 => priority += 1,
+            1 => {
+                let count = counts[owners[0]] as usize;
+                // Attacking is worth a bit more than defending
+                priority += if owners[0] == player as usize { get_line_weight(count + 1) * 2 } else { get_line_weight(count + 1) };
+            },
+            _ => {},
+        }
+    }
+
+    return priority;
+}
+
+pub fn get_ordered_moves(player: u8, search: &Search) -> Vec<usize> {
+    let mut moves: Vec<(i64, usize)> = get_candidate_moves(search).into_iter().map(|id| (get_move_priority(id, player, search), id)).collect();
+    moves.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    moves.truncate(MAX_BRANCHING);
+
+    return moves.into_iter().map(|(_, id)| id).collect();
+}
+
+// Paranoid minimax: every opponent plays against `me`
+fn alpha_beta(player: u8, depth: i32, mut alpha: i64, mut beta: i64, search: &mut Search, cancelled: &AtomicBool) -> i64 {
+    if depth == 0 || cancelled.load(Ordering::Relaxed) {
+        return search.score;
+    }
+
+    // The same position may have been reached through other moves
+    let hash = zobrist::get_hash(player, &mut search.hasher);
+    let (first_alpha, first_beta) = (alpha, beta);
+    let mut stored_move = None;
+    match zobrist::probe(hash, &search.transpositions) {
+        Some(entry) => {
+            stored_move = entry.best_move.map(|canonical| zobrist::from_canonical_move(canonical, &mut search.hasher));
+            if entry.depth >= depth {
+                match entry.bound {
+                    zobrist::Bound::Exact => return entry.score,
+                    zobrist::Bound::Lower => alpha = alpha.max(entry.score),
+                    zobrist::Bound::Upper => beta = beta.min(entry.score),
+                }
+                if alpha >= beta {
+                    return entry.score;
+                }
+            }
+        },
+        None => {},
+    }
+
+    let mut moves = get_ordered_moves(player, search);
+    if moves.is_empty() {
+        return 0; // The cube is full
+    }
+
+    // The best move found last time is tried first
+    match stored_move {
+        Some(id) if search.board.blocks[id] == BlockType::None && get_candidate_moves(search).contains(&id) => {
+            moves.retain(|other| *other != id);
+            moves.insert(0, id);
+        },
+        _ => {},
+    }
+
+    let maximizing = player == search.me;
+    let next = rules::get_next_player(player, search.settings);
+    let mut best = if maximizing { i64::MIN } else { i64::MAX };
+    let mut best_move = moves[0];
+
+    for id in moves {
+        let won = make_move(id, player, search);
+        // Wins found with more depth left are closer, so better
+        let value = if !won {
+            alpha_beta(next, depth - 1, alpha, beta, search, cancelled)
+        }
+        else if maximizing {
+            WIN_SCORE + depth as i64
+        }
+        else {
+            -WIN_SCORE - depth as i64
+        };
+        unmake_move(id, player, search);
+
+        if (maximizing && value > best) || (!maximizing && value < best) {
+            best = value;
+            best_move = id;
+        }
+        if maximizing {
+            alpha = alpha.max(value);
+        }
+        else {
+            beta = beta.min(value);
+        }
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    // A cancelled search returns wrong scores, don't keep them
+    if !cancelled.load(Ordering::Relaxed) {
+        let bound = if best <= first_alpha {
+            zobrist::Bound::Upper
+        }
+        else if best >= first_beta {
+            zobrist::Bound::Lower
+        }
+        else {
+            zobrist::Bound::Exact
+        };
+
+        zobrist::store(zobrist::TableEntry {
+            hash,
+            depth,
+            score: best,
+            bound,
+            best_move: Some(zobrist::to_canonical_move(best_move, &mut search.hasher)),
+        }, &mut search.transpositions);
+    }
+
+    return best;
+}
+
+// Best position for `player` with a search of `config.depth` moves, None if the cube is full or the search was cancelled
+pub fn choose_move(board: &Board, player: u8, config: &AlphaBetaConfig, settings: &GameSettings, cancelled: &AtomicBool) -> Option<Vec3i> {
+    return analyse_position(board, player, config, settings, cancelled).map(|(pos, _)| pos);
+}
+
+// Same search, also returns its score for `player`: above WIN_SCORE for a forced win, below -WIN_SCORE for a forced loss
+pub fn analyse_position(board: &Board, player: u8, config: &AlphaBetaConfig, settings: &GameSettings, cancelled: &AtomicBool) -> Option<(Vec3i, i64)> {
+    let depth = config.depth;
+    let masks = bitboard::build_line_masks(&board.config, settings.count_to_win);
+    let mut search = new_search(board, player, &masks, settings);
+    search.hasher = zobrist::new_hasher(board, settings, config.use_symmetries);
+    search.transpositions = zobrist::new_transposition_table(TRANSPOSITION_TABLE_SIZE_LOG2);
+    let next = rules::get_next_player(player, settings);
+
+    let mut best = None;
+    let mut alpha = i64::MIN;
+    for id in get_ordered_moves(player, &search) {
+        let won = make_move(id, player, &mut search);
+        let value = if won { WIN_SCORE + depth as i64 } else { alpha_beta(next, depth - 1, alpha, i64::MAX, &mut search, cancelled) };
+        unmake_move(id, player, &mut search);
+
+        if best.is_none() || value > alpha {
+            alpha = value;
+            best = Some(id);
+        }
+    }
+
+    if cancelled.load(Ordering::Relaxed) {
+        return None;
+    }
+    return best.map(|id| (id_to_pos(id as i32, &board.config), alpha));
+}
+
+// With the pie rule, whether `player` should take the first symbol of the opponent instead of answering it.
+// Both positions are searched, a little less deep than the moves so that the choice stays quick.
+pub fn wants_swap(board: &Board, player: u8, config: &AlphaBetaConfig, settings: &GameSettings, cancelled: &AtomicBool) -> bool {
+    let opponent = rules::get_next_player(player, settings);
+    let mut swapped = board.clone();
+    for block in swapped.blocks.iter_mut() {
+        if *block == BlockType::Player(opponent) {
+            *block = BlockType::Player(player);
+        }
+    }
+
+    let config = AlphaBetaConfig { depth: config.depth.min(SWAP_DEPTH), use_symmetries: false };
+    let kept = analyse_position(board, player, &config, settings, cancelled);
+    let taken = analyse_position(&swapped, opponent, &config, settings, cancelled);
+    match (kept, taken) {
+        (Some((_, kept)), Some((_, taken))) => return -taken > kept, // The score of the opponent, seen by the player
+        _ => return false,
+    }
+}
+
+pub fn default_alpha_beta_config() -> AlphaBetaConfig {
+    return AlphaBetaConfig { depth: DEFAULT_DEPTH, use_symmetries: false };
+}
+
+// "easy", "medium", "hard" or a depth, then ":sym" to share the positions that are symmetric
+pub fn parse_alpha_beta_config(text: &str) -> Result<AlphaBetaConfig, String> {
+    let (difficulty, use_symmetries) = match text.strip_suffix(":sym") {
+        Some(difficulty) => (difficulty, true),
+        None => (text, false),
+    };
+
+    match DIFFICULTIES.iter().find(|(name, _)| *name == difficulty) {
+        Some((_, depth)) => return Ok(AlphaBetaConfig { depth: *depth, use_symmetries }),
+        None => {},
+    }
+
+    match difficulty.parse::<i32>() {
+        Ok(depth) if (1..=MAX_DEPTH).contains(&depth) => return Ok(AlphaBetaConfig { depth, use_symmetries }),
+        _ => return Err(format!("'{}' is not a valid difficulty, expected easy, medium, hard or a depth between 1 and {}, optionally followed by :sym", text, MAX_DEPTH)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings;
+
+    fn play(moves: &[(u8, [i32; 3])], board: &mut Board) {
+        for (player, pos) in moves {
+            set_block(&vec3i_arr(*pos), BlockType::Player(*player), board);
+        }
+    }
+
+    #[test]
+    fn completes_its_own_line() {
+        let settings = settings::default_settings();
+        let mut board = new_board(&settings.board);
+        play(&[(0, [0, 0, 0]), (0, [1, 1, 1]), (0, [2, 2, 2]), (0, [3, 3, 3]), (1, [0, 1, 0]), (1, [0, 2, 0]), (1, [0, 3, 0]), (1, [0, 4, 0])], &mut board);
+
+        for depth in 1..=3 {
+            assert_eq!(choose_move(&board, 0, &AlphaBetaConfig { depth, use_symmetries: depth == 3 }, &settings, &AtomicBool::new(false)), Some(vec3i(4, 4, 4)), "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn blocks_the_opponent() {
+        let settings = settings::default_settings();
+        let mut board = new_board(&settings.board);
+        play(&[(1, [2, 0, 3]), (1, [2, 1, 3]), (1, [2, 2, 3]), (1, [2, 3, 3]), (0, [5, 5, 5]), (0, [0, 5, 0])], &mut board);
+
+        for depth in 1..=3 {
+            assert_eq!(choose_move(&board, 0, &AlphaBetaConfig { depth, use_symmetries: depth == 3 }, &settings, &AtomicBool::new(false)), Some(vec3i(2, 4, 3)), "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn finds_a_move_with_gravity_and_several_players() {
+        let mut settings = settings::default_settings();
+        settings.players = settings::get_player_presets(3);
+        settings.turn_order = vec![0, 1, 2];
+        settings.placement = settings::parse_placement("-y").unwrap();
+        let board = new_board(&settings.board);
+
+        let pos = choose_move(&board, 2, &default_alpha_beta_config(), &settings, &AtomicBool::new(false)).expect("The cube is empty");
+        assert_eq!(pos.y, 0);
+    }
+
+    #[test]
+    fn takes_a_strong_first_move() {
+        let mut settings = settings::default_settings();
+        settings.board = BoardConfig { size: [3, 3, 3], wrap: [false, false, false] };
+        settings.count_to_win = 3;
+        settings.pie_rule = true;
+
+        let mut board = new_board(&settings.board);
+        play(&[(0, [1, 1, 1])], &mut board);
+        assert!(wants_swap(&board, 1, &default_alpha_beta_config(), &settings, &AtomicBool::new(false)));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::board::id_to_pos;
+use crate::engine;
+use crate::rng;
+use crate::rules;
+use crate::rules::GameState;
+use crate::settings::{Controller, GameSettings};
+use crate::util::*;
+use std::sync::atomic::AtomicBool;
+
+// Games between engines without any display, for the tournaments and the self-play tools
+
+pub const DEFAULT_RANDOM_MOVES: usize = 2; // So that the games differ
+
+pub struct PlayedGame {
+    pub game: rules::Game, // Over, unless someone forfeited
+    pub forfeit: Option<u8>, // Player whose engine found no move, the game stopped there
+}
+
+// The first `random_moves` moves are random, then each player is played by its controller.
+// With the pie rule, the swap is offered to the engine of the second player even after a random first move.
+// Everything random comes from the seed, so the same seed gives the same game with deterministic engines.
+pub fn play_game(settings: &GameSettings, controllers: &[Controller], random_moves: usize, seed: u64) -> PlayedGame {
+    let mut game = rules::new_game(settings.clone());
+    let mut rng = rng::new_rng(seed);
+    let cancelled = AtomicBool::new(false);
+
+    loop {
+        let player = match game.state {
+            GameState::Turn(player) => player,
+            GameState::GameWon(_) | GameState::Draw => return PlayedGame { game, forfeit: None },
+        };
+
+        let move_number = game.history.len();
+        if rules::can_swap(&game) && engine::wants_swap(&game.board, player, &controllers[player as usize], &game.settings, &cancelled) {
+            rules::swap_sides(move_number as f32, &mut game);
+            continue;
+        }
+
+        let chosen = if move_number < random_moves {
+            get_random_move(&game, &mut rng)
+        }
+        else {
+            engine::run_seeded_engine(&game.board, player, &controllers[player as usize], &game.settings, &mut rng, &cancelled)
+        };
+
+        // The move number is used as the time, there is no clock here
+        match chosen.and_then(|pos| rules::play_at(&pos, move_number as f32, &mut game)) {
+            Some(_) => {},
+            None => return PlayedGame { game, forfeit: Some(player) },
+        }
+    }
+}
+
+// The winner, None for a draw. A forfeit is a win for the other player when there are two of them.
+pub fn get_winner(played: &PlayedGame) -> Option<u8> {
+    match played.forfeit {
+        Some(player) => {
+            if played.game.settings.players.len() == 2 {
+                return Some(1 - player);
+            }
+            return None;
+        },
+        None => {},
+    }
+
+    match &played.game.state {
+        GameState::GameWon(lines) => match lines[0].winner {
+            rules::BlockType::Player(id) => return Some(id),
+            rules::BlockType::None => return None,
+        },
+        GameState::Turn(_) | GameState::Draw => return None,
+    }
+}
+
+// Any block where a symbol can land
+pub fn get_random_move(game: &rules::Game, rng: &mut rng::Rng) -> Option<Vec3i> {
+    let mut choices = Vec::new();
+    for id in 0..game.board.blocks.len() as i32 {
+        let pos = id_to_pos(id, &game.board.config);
+        if rules::get_landing_pos(&pos, &game.board, &game.settings) == Some(pos.clone()) {
+            choices.push(pos);
+        }
+    }
+
+    if choices.is_empty() {
+        return None;
+    }
+    return Some(choices.swap_remove(rng.below(choices.len())));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::*;
+    use crate::test_util::small_settings;
+
+    #[test]
+    fn same_seed_same_game() {
+        let settings = small_settings();
+        let controllers = vec![parse_controller("mcts:50").unwrap(), parse_controller("mcts:50").unwrap()];
+
+        let first = play_game(&settings, &controllers, 2, 7);
+        let second = play_game(&settings, &controllers, 2, 7);
+
+        let positions = |played: &PlayedGame| played.game.history.iter().map(|played| played.position.clone()).collect::<Vec<_>>();
+        assert_eq!(positions(&first), positions(&second));
+        assert!(!matches!(first.game.state, GameState::Turn(_)));
+    }
+
+    #[test]
+    fn player_without_engine_forfeits() {
+        let settings = small_settings();
+        let controllers = vec![parse_controller("1").unwrap(), Controller::Human];
+        let played = play_game(&settings, &controllers, 0, 1);
+
+        assert_eq!(played.forfeit, Some(1));
+        assert_eq!(played.game.history.len(), 1);
+        assert_eq!(get_winner(&played), Some(0));
+    }
+
+    #[test]
+    fn engines_swap_after_a_random_first_move() {
+        let settings = GameSettings { pie_rule: true, ..small_settings() };
+        let controllers = vec![parse_controller("medium").unwrap(), parse_controller("medium").unwrap()];
+
+        // The tournament defaults: the engine is asked about the swap in every game, and takes the good first moves
+        let games: Vec<PlayedGame> = (1..9).map(|seed| play_game(&settings, &controllers, DEFAULT_RANDOM_MOVES, seed)).collect();
+        let swapped: Vec<&PlayedGame> = games.iter().filter(|played| played.game.history.len() > 1 && played.game.history[1].swap).collect();
+        assert!(!swapped.is_empty());
+        for played in swapped {
+            assert_eq!(played.game.history[1].player, 1);
+            assert_eq!(played.game.history[1].position, played.game.history[0].position);
+        }
+    }
+}
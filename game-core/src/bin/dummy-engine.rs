@@ -0,0 +1,103 @@
+// Reference engine for the text protocol: plays a random block among the ones that can be played.
+// Start from this to connect another bot, see protocol.rs for the messages.
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use game_core::board::*;
+use game_core::protocol::{self, GameMessage};
+use game_core::rules::{self, BlockType};
+use game_core::settings::{self, GameSettings};
+use game_core::util::Vec3i;
+use game_core::{notation, rng};
+use std::io::{BufRead, Write};
+
+fn main() {
+    let mut settings = settings::default_settings();
+    let mut board = new_board(&settings.board);
+    let mut has_rules = false;
+    let mut rng = rng::rng_from_time();
+
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let message = match protocol::parse_game_message(&line) {
+            Ok(Some(message)) => message,
+            Ok(None) => continue, // Newer than this engine
+            Err(msg) => {
+                let _ = writeln!(stdout, "info {}", msg);
+                continue;
+            },
+        };
+
+        match message {
+            GameMessage::Protocol(version) => {
+                if version != protocol::PROTOCOL_VERSION {
+                    let _ = writeln!(stdout, "info Made for version {} of the protocol", protocol::PROTOCOL_VERSION);
+                }
+                let _ = writeln!(stdout, "name Dummy");
+                let _ = writeln!(stdout, "ready");
+            },
+            GameMessage::Rules(rules) => {
+                board = new_board(&rules.board);
+                settings = rules;
+                has_rules = true;
+            },
+            GameMessage::Position(symbols) => {
+                // A bad position is refused, the board then stays empty
+                board = new_board(&settings.board);
+                match read_position(&symbols, has_rules, &settings) {
+                    Ok(position) => board = position,
+                    Err(msg) => {
+                        let _ = writeln!(stdout, "info {}", msg);
+                    },
+                }
+            },
+            GameMessage::Go { .. } => {
+                // Every block where a symbol can land, once each
+                let mut choices = Vec::new();
+                for id in 0..board.blocks.len() as i32 {
+                    let pos = id_to_pos(id, &board.config);
+                    if rules::get_landing_pos(&pos, &board, &settings) == Some(pos.clone()) {
+                        choices.push(pos);
+                    }
+                }
+
+                if choices.is_empty() {
+                    let _ = writeln!(stdout, "info The cube is full");
+                }
+                else {
+                    let chosen = &choices[rng.below(choices.len())];
+                    let _ = writeln!(stdout, "move {}", notation::pos_to_notation(chosen));
+                }
+            },
+            GameMessage::Pie { .. } => {
+                // Any side will do for random moves
+                let answer = if rng.below(2) == 0 { "swap" } else { "keep" };
+                let _ = writeln!(stdout, "{}", answer);
+            },
+            GameMessage::Quit => break,
+        }
+
+        let _ = stdout.flush();
+    }
+}
+
+fn read_position(symbols: &[(u8, Vec3i)], has_rules: bool, settings: &GameSettings) -> Result<Board, String> {
+    if !has_rules {
+        return Err("Got a position before the rules".to_string());
+    }
+
+    let mut board = new_board(&settings.board);
+    for (player, pos) in symbols {
+        if *player as usize >= settings.players.len() || !is_in_cube(pos, &board.config) {
+            return Err(format!("{} is not on the board of these rules", notation::move_to_notation(*player, pos)));
+        }
+        set_block(pos, BlockType::Player(*player), &mut board);
+    }
+
+    return Ok(board);
+}
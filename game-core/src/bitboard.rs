@@ -0,0 +1,259 @@
+use crate::board::*;
+use crate::rules::{BlockType, LINE_DIRECTIONS};
+use crate::settings::MAX_PLAYER_COUNT;
+use crate::util::*;
+use std::collections::HashSet;
+
+pub const WORD_COUNT: usize = 16; // Enough for the largest cube, 10x10x10
+
+// One bit per block, in the order of the block ids
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BitSet {
+    pub words: [u64; WORD_COUNT],
+}
+
+// The symbols of each player, and all of them together
+#[derive(Clone, PartialEq, Debug)]
+pub struct BitBoard {
+    pub players: [BitSet; MAX_PLAYER_COUNT],
+    pub occupied: BitSet,
+}
+
+// Every line that can win the game, wrapped ones included
+pub struct LineMasks {
+    pub masks: Vec<BitSet>,
+    pub lines_of_block: Vec<Vec<usize>>, // Lines that go through each block
+}
+
+pub fn empty_bitset() -> BitSet {
+    return BitSet { words: [0; WORD_COUNT] };
+}
+
+pub fn set_bit(id: usize, set: &mut BitSet) {
+    set.words[id / 64] |= 1 << (id % 64);
+}
+
+pub fn clear_bit(id: usize, set: &mut BitSet) {
+    set.words[id / 64] &= !(1 << (id % 64));
+}
+
+pub fn has_bit(id: usize, set: &BitSet) -> bool {
+    return set.words[id / 64] & (1 << (id % 64)) != 0;
+}
+
+// Are all the bits of `mask` in `set`?
+pub fn contains_all(set: &BitSet, mask: &BitSet) -> bool {
+    return set.words.iter().zip(&mask.words).all(|(s, m)| s & m == *m);
+}
+
+pub fn intersects(a: &BitSet, b: &BitSet) -> bool {
+    return a.words.iter().zip(&b.words).any(|(x, y)| x & y != 0);
+}
+
+pub fn count_bits(set: &BitSet) -> u32 {
+    return set.words.iter().map(|word| word.count_ones()).sum();
+}
+
+// The ids of the bits that are set, in order
+pub fn get_ids(set: &BitSet) -> Vec<usize> {
+    let mut res = Vec::new();
+    for (i, word) in set.words.iter().enumerate() {
+        let mut bits = *word;
+        while bits != 0 {
+            res.push(i * 64 + bits.trailing_zeros() as usize);
+            bits &= bits - 1;
+        }
+    }
+    return res;
+}
+
+// Block ids of every line of `count_to_win` blocks, a line that goes all around the cube is only kept once
+pub fn get_winning_lines(config: &BoardConfig, count_to_win: i32) -> Vec<Vec<usize>> {
+    let mut lines = Vec::new();
+    let mut seen = HashSet::new();
+
+    for id in 0..get_block_count(config) {
+        let start = id_to_pos(id as i32, config);
+
+        for delta in LINE_DIRECTIONS {
+            if get_line_period(&vec3i_arr(delta), config) < count_to_win {
+                continue;
+            }
+
+            let mut line = Vec::with_capacity(count_to_win as usize);
+            for i in 0..count_to_win {
+                let pos = vec3i(start.x + delta[0] * i, start.y + delta[1] * i, start.z + delta[2] * i);
+                let block_id = pos_to_id(&pos, config);
+                if block_id < 0 { // Falls outside of the cube
+                    break;
+                }
+                line.push(block_id as usize);
+            }
+            if line.len() < count_to_win as usize {
+                continue;
+            }
+
+            let mut sorted = line.clone();
+            sorted.sort();
+            if seen.insert(sorted) {
+                lines.push(line);
+            }
+        }
+    }
+
+    return lines;
+}
+
+pub fn build_line_masks(config: &BoardConfig, count_to_win: i32) -> LineMasks {
+    let mut masks = Vec::new();
+    let mut lines_of_block = vec![Vec::new(); get_block_count(config)];
+
+    for (i, line) in get_winning_lines(config, count_to_win).into_iter().enumerate() {
+        let mut mask = empty_bitset();
+        for id in line {
+            set_bit(id, &mut mask);
+            lines_of_block[id].push(i);
+        }
+        masks.push(mask);
+    }
+
+    return LineMasks { masks, lines_of_block };
+}
+
+pub fn new_bitboard() -> BitBoard {
+    return BitBoard { players: [empty_bitset(); MAX_PLAYER_COUNT], occupied: empty_bitset() };
+}
+
+pub fn board_to_bitboard(board: &Board) -> BitBoard {
+    let mut bits = new_bitboard();
+    for (id, block) in board.blocks.iter().enumerate() {
+        match block {
+            BlockType::Player(player) => make_move(id, *player, &mut bits),
+            BlockType::None => {},
+        }
+    }
+
+    return bits;
+}
+
+pub fn make_move(id: usize, player: u8, bits: &mut BitBoard) {
+    set_bit(id, &mut bits.players[player as usize]);
+    set_bit(id, &mut bits.occupied);
+}
+
+pub fn unmake_move(id: usize, player: u8, bits: &mut BitBoard) {
+    clear_bit(id, &mut bits.players[player as usize]);
+    clear_bit(id, &mut bits.occupied);
+}
+
+// Does a line through `id` belong entirely to `player`? Usually asked right after a move.
+pub fn is_win_at(id: usize, player: u8, bits: &BitBoard, masks: &LineMasks) -> bool {
+    let own = &bits.players[player as usize];
+    return masks.lines_of_block[id].iter().any(|line| contains_all(own, &masks.masks[*line]));
+}
+
+pub fn has_won(player: u8, bits: &BitBoard, masks: &LineMasks) -> bool {
+    let own = &bits.players[player as usize];
+    return masks.masks.iter().any(|mask| contains_all(own, mask));
+}
+
+// Is there still a line that contains the symbols of only one player (or none)?
+pub fn is_win_still_possible(bits: &BitBoard, masks: &LineMasks) -> bool {
+    return masks.masks.iter().any(|mask| {
+        return bits.players.iter().filter(|own| intersects(own, mask)).count() <= 1;
+    });
+}
+
+pub fn is_full(bits: &BitBoard, config: &BoardConfig) -> bool {
+    return count_bits(&bits.occupied) as usize == get_block_count(config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules;
+    use crate::rng::*;
+    use crate::settings::GameSettings;
+    use crate::test_util::{settings_with, test_settings};
+
+    // The shared ones, and a cube large enough for every word of the bit boards
+    fn bitboard_settings() -> Vec<GameSettings> {
+        let mut res = test_settings();
+        res.push(settings_with("10", "y", 5, 3));
+        return res;
+    }
+
+    #[test]
+    fn agrees_with_the_board_on_random_positions() {
+        let mut rng = new_rng(7);
+
+        for settings in bitboard_settings() {
+            let masks = build_line_masks(&settings.board, settings.count_to_win);
+            let block_count = get_block_count(&settings.board);
+
+            for _ in 0..30 {
+                let mut board = new_board(&settings.board);
+                let filled = rng.below(block_count + 1);
+                for _ in 0..filled {
+                    let id = rng.below(block_count);
+                    board.blocks[id] = BlockType::Player(rng.below(3) as u8);
+                }
+
+                let bits = board_to_bitboard(&board);
+                let lines = rules::check_for_victory(&board, &settings);
+                for player in 0..3 {
+                    let won = lines.iter().any(|info| info.winner == BlockType::Player(player));
+                    assert_eq!(has_won(player, &bits, &masks), won);
+                }
+                assert_eq!(is_win_still_possible(&bits, &masks), rules::is_win_still_possible(&board, &settings));
+                assert_eq!(is_full(&bits, &settings.board), rules::is_board_full(&board));
+            }
+        }
+    }
+
+    #[test]
+    fn win_at_the_last_move_matches_the_incremental_check() {
+        let mut rng = new_rng(11);
+
+        for settings in bitboard_settings() {
+            let masks = build_line_masks(&settings.board, settings.count_to_win);
+            let mut board = new_board(&settings.board);
+            let mut bits = new_bitboard();
+            let mut player = 0;
+
+            let mut empty: Vec<usize> = (0..board.blocks.len()).collect();
+            while !empty.is_empty() {
+                let id = empty.swap_remove(rng.below(empty.len()));
+                let pos = id_to_pos(id as i32, &settings.board);
+                set_block(&pos, BlockType::Player(player), &mut board);
+                make_move(id, player, &mut bits);
+
+                let found = !rules::check_for_victory_at(&pos, &board, &settings).is_empty();
+                assert_eq!(is_win_at(id, player, &bits, &masks), found);
+                if found {
+                    break;
+                }
+
+                player = (player + 1) % 3;
+            }
+        }
+    }
+
+    #[test]
+    fn unmake_restores_the_position() {
+        let config = parse_board_config("10").unwrap();
+        let mut bits = new_bitboard();
+        make_move(3, 1, &mut bits);
+        let before = bits.clone();
+
+        for id in [0, 63, 64, 500, 999] {
+            make_move(id, 2, &mut bits);
+            assert!(has_bit(id, &bits.players[2]) && has_bit(id, &bits.occupied));
+            unmake_move(id, 2, &mut bits);
+        }
+
+        assert_eq!(bits, before);
+        assert_eq!(count_bits(&bits.occupied), 1);
+        assert!(!is_full(&bits, &config));
+    }
+}
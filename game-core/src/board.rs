@@ -0,0 +1,214 @@
+use crate::rules::BlockType;
+use crate::util::*;
+
+pub const MIN_ROW_COUNT: i32 = 3;
+pub const MAX_ROW_COUNT: i32 = 10;
+pub const DEFAULT_ROW_COUNT: i32 = 6;
+
+// Dimensions of the cube, chosen when the game starts
+#[derive(Clone, PartialEq)]
+pub struct BoardConfig {
+    pub size: [i32; 3], // Number of blocks along x, y and z
+    pub wrap: [bool; 3], // Do lines continue on the other side of the cube along x, y and z?
+}
+
+#[derive(Clone)]
+pub struct Board {
+    pub config: BoardConfig,
+    pub blocks: Vec<BlockType>,
+}
+
+pub fn cube_config(row_count: i32) -> BoardConfig {
+    return BoardConfig { size: [row_count, row_count, row_count], wrap: [true, true, true] };
+}
+
+// Accepts either a single number ("5") or one number per axis ("4x5x6")
+pub fn parse_board_config(text: &str) -> Result<BoardConfig, String> {
+    let parts: Vec<&str> = text.split('x').collect();
+
+    let mut numbers = Vec::with_capacity(3);
+    for part in &parts {
+        match part.trim().parse::<i32>() {
+            Ok(number) => numbers.push(number),
+            Err(_) => return Err(format!("'{}' is not a valid board size", text)),
+        }
+    }
+
+    let config = match numbers.len() {
+        1 => cube_config(numbers[0]),
+        3 => BoardConfig { size: [numbers[0], numbers[1], numbers[2]], wrap: [true, true, true] },
+        _ => return Err(format!("'{}' is not a valid board size, expected something like 6 or 4x5x6", text)),
+    };
+
+    check_board_config(&config)?;
+    return Ok(config);
+}
+
+// Accepts "torus", "bounded", or the list of the axes that wrap ("xz")
+pub fn parse_wrap(text: &str) -> Result<[bool; 3], String> {
+    match text {
+        "torus" => return Ok([true, true, true]),
+        "bounded" => return Ok([false, false, false]),
+        _ => {},
+    }
+
+    let mut wrap = [false, false, false];
+    for char in text.chars() {
+        match char {
+            'x' => wrap[0] = true,
+            'y' => wrap[1] = true,
+            'z' => wrap[2] = true,
+            _ => return Err(format!("'{}' is not a valid topology, expected torus, bounded, or the axes that wrap (like xz)", text)),
+        }
+    }
+
+    return Ok(wrap);
+}
+
+pub fn wrap_to_string(wrap: &[bool; 3]) -> String {
+    if wrap.iter().all(|w| *w) {
+        return "torus".to_string();
+    }
+    if wrap.iter().all(|w| !*w) {
+        return "bounded".to_string();
+    }
+
+    return ['x', 'y', 'z'].into_iter().zip(wrap).filter(|(_, w)| **w).map(|(axis, _)| axis).collect();
+}
+
+pub fn board_config_to_string(config: &BoardConfig) -> String {
+    return format!("{}x{}x{}", config.size[0], config.size[1], config.size[2]);
+}
+
+pub fn check_board_config(config: &BoardConfig) -> Result<(), String> {
+    for size in config.size {
+        if !(MIN_ROW_COUNT..=MAX_ROW_COUNT).contains(&size) {
+            return Err(format!("Board sides must be between {} and {} blocks, got {}", MIN_ROW_COUNT, MAX_ROW_COUNT, size));
+        }
+    }
+
+    return Ok(());
+}
+
+pub fn get_block_count(config: &BoardConfig) -> usize {
+    return (config.size[0] * config.size[1] * config.size[2]) as usize;
+}
+
+pub fn get_max_row_count(config: &BoardConfig) -> i32 {
+    return config.size[0].max(config.size[1]).max(config.size[2]);
+}
+
+// Number of steps along `direction` before coming back to the starting block
+pub fn get_line_period(direction: &Vec3i, config: &BoardConfig) -> i32 {
+    let mut period = 1;
+    for axis in 0..3 {
+        let delta = vec3i_to_arr(direction)[axis];
+        if delta == 0 {
+            continue;
+        }
+
+        // Never comes back, the line will fall outside of the cube instead
+        if !config.wrap[axis] {
+            return i32::MAX;
+        }
+
+        period = lcm(period, config.size[axis]);
+    }
+
+    return period;
+}
+
+fn lcm(a: i32, b: i32) -> i32 {
+    let mut x = a;
+    let mut y = b;
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+
+    return a / x * b;
+}
+
+pub fn new_board(config: &BoardConfig) -> Board {
+    return Board {
+        config: config.clone(),
+        blocks: vec![BlockType::None; get_block_count(config)],
+    };
+}
+
+// Brings coordinates back into the cube along the axes that wrap
+pub fn wrap_pos(pos: &Vec3i, config: &BoardConfig) -> Vec3i {
+    let mut res = vec3i_to_arr(pos);
+    for (axis, coordinate) in res.iter_mut().enumerate() {
+        if config.wrap[axis] {
+            *coordinate = coordinate.rem_euclid(config.size[axis]);
+        }
+    }
+
+    return vec3i_arr(res);
+}
+
+pub fn is_in_cube(pos: &Vec3i, config: &BoardConfig) -> bool {
+    return pos.x >= 0 && pos.x < config.size[0]
+        && pos.y >= 0 && pos.y < config.size[1]
+        && pos.z >= 0 && pos.z < config.size[2];
+}
+
+// Coordinates outside of the cube wrap around if the axis allows it, otherwise returns -1
+pub fn pos_to_id(pos: &Vec3i, config: &BoardConfig) -> i32 {
+    let wrapped = wrap_pos(pos, config);
+    if !is_in_cube(&wrapped, config) {
+        return -1;
+    }
+
+    let size = &config.size;
+    return wrapped.x + wrapped.y * size[0] + wrapped.z * size[0] * size[1];
+}
+
+pub fn id_to_pos(id: i32, config: &BoardConfig) -> Vec3i {
+    let size = &config.size;
+
+    return vec3i(
+        id % size[0],
+        (id / size[0]) % size[1],
+        id / (size[0] * size[1]),
+    );
+}
+
+// Blocks touching each block, diagonals included
+pub fn get_neighbour_table(config: &BoardConfig) -> Vec<Vec<usize>> {
+    let mut neighbours = vec![Vec::new(); get_block_count(config)];
+    for (id, list) in neighbours.iter_mut().enumerate() {
+        let pos = id_to_pos(id as i32, config);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let other = pos_to_id(&vec3i(pos.x + dx, pos.y + dy, pos.z + dz), config);
+                    if other >= 0 && other as usize != id && !list.contains(&(other as usize)) {
+                        list.push(other as usize);
+                    }
+                }
+            }
+        }
+    }
+
+    return neighbours;
+}
+
+// Outside of a bounded cube, there is nothing
+pub fn get_block(pos: &Vec3i, board: &Board) -> BlockType {
+    let id = pos_to_id(pos, &board.config);
+    if id < 0 {
+        return BlockType::None;
+    }
+
+    return board.blocks[id as usize];
+}
+
+pub fn set_block(pos: &Vec3i, value: BlockType, board: &mut Board) {
+    let id = pos_to_id(pos, &board.config);
+    if id < 0 {
+        panic!("Tried to place a block outside of the cube");
+    }
+
+    board.blocks[id as usize] = value;
+}
@@ -0,0 +1,228 @@
+use crate::board::*;
+use crate::notation;
+use crate::rng;
+use crate::rules::{self, BlockType, Move};
+use crate::settings::GameSettings;
+use crate::util::*;
+use crate::zobrist;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Opening moves that worked well in earlier games, played by the engines instead of searching.
+// Positions are stored once for all their symmetries, with the moves as seen through the same symmetry.
+
+pub const BOOK_VERSION: u32 = 1;
+pub const DEFAULT_BOOK_PATH: &str = "./book.json";
+pub const WIN_WEIGHT: u32 = 2; // Added to a move for each game its player won
+pub const DRAW_WEIGHT: u32 = 1; // Lost games add nothing
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct OpeningBook {
+    pub version: u32,
+    pub positions: BTreeMap<String, Vec<BookMove>>, // By zobrist::get_position_key
+    #[serde(skip)] // Found again from the positions when the book is loaded
+    pub deepest: usize, // Most symbols on the board of a position, the games past it don't look the book up
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BookMove {
+    #[serde(rename = "move")]
+    pub notation: String, // Like c2.4, on the canonical board
+    pub weight: u32, // The move is chosen with a probability proportional to it
+}
+
+pub fn new_book() -> OpeningBook {
+    return OpeningBook { version: BOOK_VERSION, positions: BTreeMap::new(), deepest: 0 };
+}
+
+// The moves of the book for this position that can be played, with their weights
+pub fn get_book_moves(board: &Board, player: u8, settings: &GameSettings, book: &OpeningBook) -> Vec<(Vec3i, u32)> {
+    // Much cheaper than the key, which goes through every symmetry
+    if count_symbols(board) > book.deepest {
+        return Vec::new();
+    }
+
+    let (key, symmetry) = zobrist::get_position_key(board, player, settings);
+    let moves = match book.positions.get(&key) {
+        Some(moves) => moves,
+        None => return Vec::new(),
+    };
+
+    let mut res = Vec::new();
+    for book_move in moves {
+        // A hand-edited book could have anything here
+        let canonical = match notation::parse_pos(&book_move.notation) {
+            Ok(pos) if is_in_cube(&pos, &board.config) => pos_to_id(&pos, &board.config) as u16,
+            _ => continue,
+        };
+        let id = symmetry.iter().position(|image| *image == canonical).expect("Symmetries are permutations");
+        let pos = id_to_pos(id as i32, &board.config);
+
+        if book_move.weight > 0 && rules::get_landing_pos(&pos, board, settings) == Some(pos.clone()) {
+            res.push((pos, book_move.weight));
+        }
+    }
+
+    return res;
+}
+
+// A random move of the book, the heavier the likelier. None when the position is not in the book.
+pub fn choose_move(board: &Board, player: u8, settings: &GameSettings, book: &OpeningBook, rng: &mut rng::Rng) -> Option<Vec3i> {
+    let moves = get_book_moves(board, player, settings, book);
+    let total: u32 = moves.iter().map(|(_, weight)| weight).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut chosen = rng.below(total as usize) as u32;
+    for (pos, weight) in moves {
+        if chosen < weight {
+            return Some(pos);
+        }
+        chosen -= weight;
+    }
+
+    return None;
+}
+
+// Adds `weight` to the move of `player` on `pos`, the move is added if it wasn't there
+pub fn add_move(board: &Board, player: u8, pos: &Vec3i, weight: u32, settings: &GameSettings, book: &mut OpeningBook) {
+    let (key, symmetry) = zobrist::get_position_key(board, player, settings);
+    let id = pos_to_id(pos, &board.config) as usize;
+
+    // When the position is symmetric, several symmetries lead to the canonical board: equivalent moves are merged
+    let canonical_text = zobrist::get_board_text(board, &symmetry);
+    let canonical = zobrist::get_symmetries(&board.config, &settings.placement).iter()
+        .filter(|map| zobrist::get_board_text(board, map) == canonical_text)
+        .map(|map| map[id])
+        .min()
+        .expect("The symmetry of the key is one of them");
+    let text = notation::pos_to_notation(&id_to_pos(canonical as i32, &board.config));
+
+    book.deepest = book.deepest.max(count_symbols(board));
+    let moves = book.positions.entry(key).or_default();
+    match moves.iter_mut().find(|book_move| book_move.notation == text) {
+        Some(book_move) => book_move.weight += weight,
+        None => moves.push(BookMove { notation: text, weight }),
+    }
+}
+
+// Learns the first `max_plies` moves of a finished game, `winner` is None for a draw
+pub fn add_game(moves: &[Move], winner: Option<u8>, max_plies: usize, settings: &GameSettings, book: &mut OpeningBook) {
+    let mut board = new_board(&settings.board);
+    for played in moves.iter().take(max_plies) {
+        // Swaps are not in the book, the position after them is
+        let weight = match winner {
+            _ if played.swap => 0,
+            Some(player) if player == played.player => WIN_WEIGHT,
+            Some(_) => 0,
+            None => DRAW_WEIGHT,
+        };
+        if weight > 0 {
+            add_move(&board, played.player, &played.position, weight, settings, book);
+        }
+
+        set_block(&played.position, BlockType::Player(played.player), &mut board);
+    }
+}
+
+pub fn load_book(path: &str) -> Result<OpeningBook, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| format!("Couldn't read '{}': {}", path, err))?;
+    let mut book: OpeningBook = serde_json::from_str(&content).map_err(|err| format!("Invalid opening book '{}': {}", path, err))?;
+    if book.version != BOOK_VERSION {
+        return Err(format!("Opening book '{}' has version {}, expected {}", path, book.version, BOOK_VERSION));
+    }
+
+    // The board of a key is the word after the rules, one letter per block
+    for key in book.positions.keys() {
+        let symbols = match key.rsplit_once(" / ").and_then(|(_, position)| position.split(' ').next()) {
+            Some(text) => text.chars().filter(|letter| *letter != '.').count(),
+            None => return Err(format!("Invalid opening book '{}': '{}' is not a position", path, key)),
+        };
+        book.deepest = book.deepest.max(symbols);
+    }
+    return Ok(book);
+}
+
+fn count_symbols(board: &Board) -> usize {
+    return board.blocks.iter().filter(|block| **block != BlockType::None).count();
+}
+
+pub fn save_book(path: &str, book: &OpeningBook) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(book).map_err(|err| err.to_string())?;
+    return std::fs::write(path, content).map_err(|err| format!("Couldn't write '{}': {}", path, err));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::small_settings;
+
+    #[test]
+    fn moves_are_found_in_symmetric_positions() {
+        let settings = small_settings();
+        let mut book = new_book();
+
+        // X in a corner, O answers in the center
+        let mut board = new_board(&settings.board);
+        set_block(&vec3i(0, 0, 0), BlockType::Player(0), &mut board);
+        add_move(&board, 1, &vec3i(1, 1, 1), 5, &settings, &mut book);
+
+        // Same answer to X in another corner
+        let mut other = new_board(&settings.board);
+        set_block(&vec3i(2, 0, 2), BlockType::Player(0), &mut other);
+        assert_eq!(get_book_moves(&other, 1, &settings, &book), vec![(vec3i(1, 1, 1), 5)]);
+
+        // The three blocks next to X are the same move
+        add_move(&board, 1, &vec3i(1, 0, 0), 1, &settings, &mut book);
+        add_move(&board, 1, &vec3i(0, 1, 0), 1, &settings, &mut book);
+        add_move(&board, 1, &vec3i(0, 0, 1), 1, &settings, &mut book);
+        let mut moves = get_book_moves(&other, 1, &settings, &book);
+        moves.sort_by_key(|(_, weight)| *weight);
+        assert_eq!(moves[1], (vec3i(1, 1, 1), 5));
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[0].1, 3);
+        assert!([vec3i(1, 0, 2), vec3i(2, 1, 2), vec3i(2, 0, 1)].contains(&moves[0].0));
+
+        // Not for the other player
+        assert!(get_book_moves(&other, 0, &settings, &book).is_empty());
+    }
+
+    #[test]
+    fn positions_past_the_deepest_one_are_not_looked_up() {
+        let settings = small_settings();
+        let mut book = new_book();
+        let mut board = new_board(&settings.board);
+        set_block(&vec3i(0, 0, 0), BlockType::Player(0), &mut board);
+        add_move(&board, 1, &vec3i(1, 1, 1), 1, &settings, &mut book);
+        assert_eq!(book.deepest, 1);
+
+        let path = std::env::temp_dir().join("first-test-book.json").to_string_lossy().to_string();
+        save_book(&path, &book).unwrap();
+        let loaded = load_book(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.deepest, 1);
+        assert_eq!(get_book_moves(&board, 1, &settings, &loaded).len(), 1);
+
+        set_block(&vec3i(2, 2, 2), BlockType::Player(1), &mut board);
+        assert!(get_book_moves(&board, 0, &settings, &loaded).is_empty());
+    }
+
+    #[test]
+    fn only_the_moves_of_winners_are_learnt() {
+        let settings = small_settings();
+        let mut game = rules::new_game(settings.clone());
+        for pos in [vec3i(1, 1, 1), vec3i(0, 0, 0), vec3i(1, 1, 0), vec3i(0, 0, 1), vec3i(1, 1, 2)] {
+            rules::play_at(&pos, 0.0, &mut game).unwrap();
+        }
+
+        let mut book = new_book();
+        add_game(&game.history, Some(0), 4, &settings, &mut book);
+
+        // Two moves of X, the fifth one is past the limit
+        let total: u32 = book.positions.values().flatten().map(|book_move| book_move.weight).sum();
+        assert_eq!(total, 2 * WIN_WEIGHT);
+        let mut rng = rng::new_rng(1);
+        assert_eq!(choose_move(&new_board(&settings.board), 0, &settings, &book, &mut rng), Some(vec3i(1, 1, 1)));
+    }
+}
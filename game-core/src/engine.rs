@@ -0,0 +1,71 @@
+use crate::ai;
+use crate::board::Board;
+use crate::book;
+use crate::job;
+use crate::mcts;
+use crate::protocol;
+use crate::rng;
+use crate::settings::{Controller, GameSettings};
+use crate::util::*;
+use std::sync::atomic::AtomicBool;
+
+// An engine searching on its own copy of the board, on another thread
+pub type EngineJob = job::Job<Choice>;
+
+pub enum Choice {
+    Move(Vec3i),
+    Swap, // With the pie rule, the engine takes the first move instead
+    NoMove, // The engine found nothing to play
+}
+
+// Runs the engine of `controller` on the current thread, returns None if it was cancelled
+pub fn run_engine(board: &Board, player: u8, controller: &Controller, settings: &GameSettings, cancelled: &AtomicBool) -> Option<Vec3i> {
+    return run_seeded_engine(board, player, controller, settings, &mut rng::rng_from_time(), cancelled);
+}
+
+// Same, but the random choices of the engine come from `rng`, so that the games can be replayed
+pub fn run_seeded_engine(board: &Board, player: u8, controller: &Controller, settings: &GameSettings, rng: &mut rng::Rng, cancelled: &AtomicBool) -> Option<Vec3i> {
+    // The engines of the game play the book while they can, other programs have their own
+    match (controller, &settings.book) {
+        (Controller::AlphaBeta(_) | Controller::Mcts(_), Some(opening_book)) => {
+            match book::choose_move(board, player, settings, opening_book, rng) {
+                Some(pos) => return Some(pos),
+                None => {},
+            }
+        },
+        _ => {},
+    }
+
+    match controller {
+        Controller::Human => return None,
+        Controller::AlphaBeta(config) => return ai::choose_move(board, player, config, settings, cancelled),
+        Controller::Mcts(config) => return mcts::choose_move(board, player, config, settings, rng, cancelled),
+        Controller::External(config) => return protocol::run_external_engine(board, player, config, settings, cancelled),
+    }
+}
+
+// With the pie rule, whether the engine takes the first move instead of answering it
+pub fn wants_swap(board: &Board, player: u8, controller: &Controller, settings: &GameSettings, cancelled: &AtomicBool) -> bool {
+    match controller {
+        Controller::AlphaBeta(config) => return ai::wants_swap(board, player, config, settings, cancelled),
+        Controller::Mcts(_) => return ai::wants_swap(board, player, &ai::default_alpha_beta_config(), settings, cancelled),
+        Controller::External(config) => return protocol::run_external_swap(board, player, config, settings, cancelled),
+        Controller::Human => return false,
+    }
+}
+
+// The engine may swap sides when `can_swap` is set, see rules::can_swap
+pub fn start_job(board: &Board, player: u8, controller: &Controller, settings: &GameSettings, move_number: usize, can_swap: bool) -> EngineJob {
+    let board = board.clone();
+    let controller = controller.clone();
+    let settings = settings.clone();
+    return job::start_job(player, move_number, move |cancelled| {
+        if can_swap && wants_swap(&board, player, &controller, &settings, cancelled) {
+            return Choice::Swap;
+        }
+        match run_engine(&board, player, &controller, &settings, cancelled) {
+            Some(pos) => return Choice::Move(pos),
+            None => return Choice::NoMove,
+        }
+    });
+}
@@ -0,0 +1,124 @@
+use crate::ai;
+use crate::bitboard;
+use crate::board::*;
+use crate::rules;
+use crate::rules::BlockType;
+use crate::settings::GameSettings;
+use crate::util::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HintKind {
+    Win, // Completes a line of the player
+    Block, // Stops an opponent from completing a line on their next move
+    OpenFour, // Leaves two ways to win next turn, only one can be blocked
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Hint {
+    pub position: Vec3i,
+    pub kind: HintKind,
+}
+
+// Every block worth a hint for `player`, each one with its most urgent kind
+pub fn find_hints(board: &Board, player: u8, settings: &GameSettings) -> Vec<Hint> {
+    let masks = bitboard::build_line_masks(&board.config, settings.count_to_win);
+    let mut search = ai::new_search(board, player, &masks, settings);
+
+    let wins = get_winning_blocks(player, &search);
+    let mut blocks = Vec::new();
+    for other in 0..settings.players.len() as u8 {
+        if other != player {
+            blocks.extend(get_winning_blocks(other, &search));
+        }
+    }
+
+    let mut res = Vec::new();
+    for id in 0..board.blocks.len() {
+        let kind = if wins.contains(&id) {
+            HintKind::Win
+        }
+        else if blocks.contains(&id) {
+            HintKind::Block
+        }
+        else if is_playable(id, &search) && creates_open_four(id, player, &mut search) {
+            HintKind::OpenFour
+        }
+        else {
+            continue;
+        };
+
+        res.push(Hint { position: id_to_pos(id as i32, &board.config), kind });
+    }
+
+    return res;
+}
+
+// Blocks where `player` would complete a line right now
+pub fn get_winning_blocks(player: u8, search: &ai::Search) -> Vec<usize> {
+    let mut res = Vec::new();
+    for (line, counts) in search.masks.masks.iter().zip(&search.counts) {
+        let others: u8 = counts.iter().enumerate().filter(|(p, _)| *p != player as usize).map(|(_, count)| *count).sum();
+        if others > 0 || counts[player as usize] as i32 != search.settings.count_to_win - 1 {
+            continue;
+        }
+
+        for id in bitboard::get_ids(line) {
+            if is_playable(id, search) && !res.contains(&id) {
+                res.push(id);
+            }
+        }
+    }
+
+    return res;
+}
+
+// Empty, and not floating when symbols fall
+fn is_playable(id: usize, search: &ai::Search) -> bool {
+    if search.board.blocks[id] != BlockType::None {
+        return false;
+    }
+
+    let pos = id_to_pos(id as i32, &search.board.config);
+    return rules::get_landing_pos(&pos, &search.board, search.settings) == Some(pos);
+}
+
+fn creates_open_four(id: usize, player: u8, search: &mut ai::Search) -> bool {
+    ai::make_move(id, player, search);
+    let threats = get_winning_blocks(player, search).len();
+    ai::unmake_move(id, player, search);
+
+    return threats >= 2;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings;
+
+    fn get_kind(pos: Vec3i, hints: &[Hint]) -> Option<HintKind> {
+        return hints.iter().find(|hint| hint.position == pos).map(|hint| hint.kind);
+    }
+
+    #[test]
+    fn finds_wins_blocks_and_open_fours() {
+        let mut settings = settings::default_settings();
+        settings.board.wrap = [false, false, false];
+        let mut board = new_board(&settings.board);
+
+        // Four crosses along x, four circles along y, three crosses along z with both ends free
+        for i in 0..4 {
+            set_block(&vec3i(i, 0, 0), BlockType::Player(0), &mut board);
+            set_block(&vec3i(5, i + 1, 5), BlockType::Player(1), &mut board);
+        }
+        for i in 1..4 {
+            set_block(&vec3i(2, 3, i), BlockType::Player(0), &mut board);
+        }
+
+        let hints = find_hints(&board, 0, &settings);
+        assert_eq!(get_kind(vec3i(4, 0, 0), &hints), Some(HintKind::Win));
+        assert_eq!(get_kind(vec3i(5, 0, 5), &hints), Some(HintKind::Block));
+        assert_eq!(get_kind(vec3i(5, 5, 5), &hints), Some(HintKind::Block));
+        assert_eq!(get_kind(vec3i(2, 3, 4), &hints), Some(HintKind::OpenFour));
+        assert_eq!(get_kind(vec3i(3, 3, 3), &hints), None);
+    }
+}
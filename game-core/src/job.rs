@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+// Work on its own thread while the frames go on, like an engine choosing a move or the solver
+
+pub struct Job<T> {
+    pub player: u8, // Player to move when the job started
+    pub move_number: usize, // Length of the history when the job started
+    result: mpsc::Receiver<T>,
+    cancelled: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+pub enum JobStatus<T> {
+    Running,
+    Done(T),
+    Failed, // The thread ended without a result
+}
+
+// `work` should return early once the flag it gets is set, its result is then dropped
+pub fn start_job<T: Send + 'static>(player: u8, move_number: usize, work: impl FnOnce(&AtomicBool) -> T + Send + 'static) -> Job<T> {
+    let (sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    let thread_cancelled = cancelled.clone();
+    let thread = thread::spawn(move || {
+        let result = work(&thread_cancelled);
+        if !thread_cancelled.load(Ordering::Relaxed) {
+            let _ = sender.send(result); // Nobody is waiting anymore if the job was dropped
+        }
+    });
+
+    return Job { player, move_number, result: receiver, cancelled, thread };
+}
+
+// Never blocks, meant to be called every frame
+pub fn poll_job<T>(job: &Job<T>) -> JobStatus<T> {
+    match job.result.try_recv() {
+        Ok(result) => return JobStatus::Done(result),
+        Err(mpsc::TryRecvError::Empty) => return JobStatus::Running,
+        Err(mpsc::TryRecvError::Disconnected) => return JobStatus::Failed,
+    }
+}
+
+// Asks the job to stop, without waiting for it
+pub fn cancel_job<T>(job: Job<T>) {
+    job.cancelled.store(true, Ordering::Relaxed);
+}
+
+// Asks the job to stop and waits for its thread to end, when closing the game
+pub fn stop_job<T>(job: Job<T>) {
+    job.cancelled.store(true, Ordering::Relaxed);
+    let _ = job.thread.join();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_come_once_and_cancelled_jobs_stop() {
+        let job = start_job(1, 3, |_| 42);
+        let mut status = poll_job(&job);
+        while matches!(status, JobStatus::Running) {
+            thread::yield_now();
+            status = poll_job(&job);
+        }
+        assert!(matches!(status, JobStatus::Done(42)));
+        assert_eq!((job.player, job.move_number), (1, 3));
+
+        // Would never end without the flag
+        let job = start_job(0, 0, |cancelled| {
+            while !cancelled.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            return 42;
+        });
+        assert!(matches!(poll_job(&job), JobStatus::Running));
+        stop_job(job);
+    }
+}
@@ -0,0 +1,24 @@
+// Everything that doesn't need a window: the board, the rules, the engines and the save files
+// Same style as the game: explicit returns and `match`
+#![allow(clippy::needless_return, clippy::single_match)]
+
+pub mod ai;
+pub mod arena;
+pub mod bitboard;
+pub mod book;
+pub mod board;
+pub mod engine;
+pub mod hints;
+pub mod job;
+pub mod mcts;
+pub mod notation;
+pub mod protocol;
+pub mod record;
+pub mod rng;
+pub mod rules;
+pub mod settings;
+pub mod solver;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod util;
+pub mod zobrist;
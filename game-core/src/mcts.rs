@@ -0,0 +1,215 @@
+use crate::ai;
+use crate::bitboard;
+use crate::board::*;
+use crate::rules;
+use crate::rng::*;
+use crate::settings::GameSettings;
+use crate::util::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const DEFAULT_PLAYOUT_COUNT: u32 = 2000;
+pub const DEFAULT_EXPLORATION: f32 = 1.4; // About sqrt(2), the usual value for rewards between 0 and 1
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MctsConfig {
+    pub playout_count: u32,
+    pub exploration: f32, // Higher values try more moves, lower values dig deeper into the good ones
+}
+
+pub fn default_mcts_config() -> MctsConfig {
+    return MctsConfig { playout_count: DEFAULT_PLAYOUT_COUNT, exploration: DEFAULT_EXPLORATION };
+}
+
+struct Node {
+    block_id: usize, // Move that leads to this node
+    player: u8, // Who played that move
+    parent: usize,
+    children: Vec<usize>,
+    untried: Vec<usize>, // Moves that don't have a child yet
+    visits: u32,
+    reward: f32, // Sum of the results of the playouts, for `player`
+    is_end: bool, // The move won the game, or filled the cube
+}
+
+// Best position for `player` after `config.playout_count` random games, None if the cube is full or the search was cancelled
+pub fn choose_move(board: &Board, player: u8, config: &MctsConfig, settings: &GameSettings, rng: &mut Rng, cancelled: &AtomicBool) -> Option<Vec3i> {
+    let masks = bitboard::build_line_masks(&board.config, settings.count_to_win);
+    let mut search = ai::new_search(board, player, &masks, settings);
+
+    // The root stands for the move that was played before, by the previous player
+    let previous = *settings.turn_order.iter().find(|id| rules::get_next_player(**id, settings) == player).expect("Player is not in the turn order");
+    let mut nodes = vec![Node {
+        block_id: 0,
+        player: previous,
+        parent: 0,
+        children: Vec::new(),
+        untried: ai::get_candidate_moves(&search),
+        visits: 0,
+        reward: 0.0,
+        is_end: false,
+    }];
+    if nodes[0].untried.is_empty() {
+        return None;
+    }
+
+    for _ in 0..config.playout_count {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let mut played = Vec::new();
+        let mut current = 0;
+        let mut winner = None;
+
+        // Selection: follow the best children until a node can still be expanded
+        while nodes[current].untried.is_empty() && !nodes[current].children.is_empty() {
+            current = select_child(current, config.exploration, &nodes);
+            play(nodes[current].block_id, nodes[current].player, &mut search, &mut played, &mut winner);
+        }
+
+        // Expansion
+        if !nodes[current].is_end && !nodes[current].untried.is_empty() {
+            let index = rng.below(nodes[current].untried.len());
+            let block_id = nodes[current].untried.swap_remove(index);
+            let mover = rules::get_next_player(nodes[current].player, settings);
+            play(block_id, mover, &mut search, &mut played, &mut winner);
+
+            let untried = if winner.is_some() { Vec::new() } else { ai::get_candidate_moves(&search) };
+            nodes.push(Node {
+                block_id,
+                player: mover,
+                parent: current,
+                children: Vec::new(),
+                is_end: winner.is_some() || untried.is_empty(),
+                untried,
+                visits: 0,
+                reward: 0.0,
+            });
+            let child = nodes.len() - 1;
+            nodes[current].children.push(child);
+            current = child;
+        }
+
+        // Simulation: random moves until the end of the game
+        if !nodes[current].is_end {
+            let mut mover = rules::get_next_player(nodes[current].player, settings);
+            let mut empty: Vec<usize> = (0..search.board.blocks.len()).filter(|id| !bitboard::has_bit(*id, &search.bits.occupied)).collect();
+
+            while winner.is_none() && !empty.is_empty() {
+                let index = rng.below(empty.len());
+                let chosen = id_to_pos(empty[index] as i32, &search.board.config);
+                let landing = rules::get_landing_pos(&chosen, &search.board, settings).expect("The block is empty");
+                let block_id = pos_to_id(&landing, &search.board.config) as usize;
+                empty.retain(|id| *id != block_id);
+
+                play(block_id, mover, &mut search, &mut played, &mut winner);
+                mover = rules::get_next_player(mover, settings);
+            }
+        }
+
+        // Backpropagation: a win is worth 1, a draw is shared
+        let player_count = settings.players.len() as f32;
+        loop {
+            let node = &mut nodes[current];
+            node.visits += 1;
+            node.reward += match winner {
+                Some(id) if id == node.player => 1.0,
+                Some(_) => 0.0,
+                None => 1.0 / player_count,
+            };
+
+            if current == 0 {
+                break;
+            }
+            current = node.parent;
+        }
+
+        for (block_id, mover) in played.into_iter().rev() {
+            ai::unmake_move(block_id, mover, &mut search);
+        }
+    }
+
+    // The most visited move is the most reliable one
+    let best = nodes[0].children.iter().max_by_key(|child| nodes[**child].visits).expect("The root has children");
+    return Some(id_to_pos(nodes[*best].block_id as i32, &board.config));
+}
+
+fn play(block_id: usize, player: u8, search: &mut ai::Search, played: &mut Vec<(usize, u8)>, winner: &mut Option<u8>) {
+    if ai::make_move(block_id, player, search) {
+        *winner = Some(player);
+    }
+    played.push((block_id, player));
+}
+
+// Upper confidence bound applied to trees
+fn select_child(parent: usize, exploration: f32, nodes: &[Node]) -> usize {
+    let log_visits = (nodes[parent].visits as f32).ln();
+    let mut best = nodes[parent].children[0];
+    let mut best_value = f32::MIN;
+
+    for child in &nodes[parent].children {
+        let node = &nodes[*child];
+        let value = node.reward / node.visits as f32 + exploration * (log_visits / node.visits as f32).sqrt();
+        if value > best_value {
+            best = *child;
+            best_value = value;
+        }
+    }
+
+    return best;
+}
+
+// "mcts", "mcts:<playouts>" or "mcts:<playouts>:<exploration>"
+pub fn parse_mcts_config(text: &str) -> Result<MctsConfig, String> {
+    let error = || format!("'{}' is not a valid MCTS engine, expected something like mcts, mcts:5000 or mcts:5000:1.4", text);
+    let parts: Vec<&str> = text.split(':').collect();
+    if parts[0] != "mcts" || parts.len() > 3 {
+        return Err(error());
+    }
+
+    let mut config = default_mcts_config();
+    if parts.len() >= 2 {
+        config.playout_count = match parts[1].parse::<u32>() {
+            Ok(count) if count >= 1 => count,
+            _ => return Err(error()),
+        };
+    }
+    if parts.len() == 3 {
+        config.exploration = match parts[2].parse::<f32>() {
+            Ok(exploration) if exploration >= 0.0 => exploration,
+            _ => return Err(error()),
+        };
+    }
+
+    return Ok(config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::BlockType;
+    use crate::settings;
+
+    #[test]
+    fn completes_its_own_line() {
+        let settings = settings::default_settings();
+        let mut board = new_board(&settings.board);
+        for i in 0..4 {
+            set_block(&vec3i(i, i, i), BlockType::Player(0), &mut board);
+            set_block(&vec3i(0, i + 1, 0), BlockType::Player(1), &mut board);
+        }
+
+        // The line wraps around the cube, so both ends complete it
+        let mut rng = new_rng(42);
+        let chosen = choose_move(&board, 0, &default_mcts_config(), &settings, &mut rng, &AtomicBool::new(false));
+        assert!(chosen == Some(vec3i(4, 4, 4)) || chosen == Some(vec3i(5, 5, 5)), "{:?}", chosen);
+    }
+
+    #[test]
+    fn config_is_parsed() {
+        assert_eq!(parse_mcts_config("mcts"), Ok(default_mcts_config()));
+        assert_eq!(parse_mcts_config("mcts:500:0.5"), Ok(MctsConfig { playout_count: 500, exploration: 0.5 }));
+        assert!(parse_mcts_config("mcts:0").is_err());
+        assert!(parse_mcts_config("mcts:500:x").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::rules::Move;
+use crate::util::*;
+
+// Moves are written like "X c2.4": the symbol of the player, then x as a letter (a is 0),
+// y as a number and z after the dot (both starting at 1)
+
+// Same order as the player presets: cross, circle, triangle, square
+pub const PLAYER_LETTERS: [char; 4] = ['X', 'O', 'T', 'S'];
+pub const SWAP_WORD: &str = "swap"; // In place of the position, for the pie rule
+
+pub fn pos_to_notation(pos: &Vec3i) -> String {
+    let column = (b'a' + pos.x as u8) as char;
+    return format!("{}{}.{}", column, pos.y + 1, pos.z + 1);
+}
+
+pub fn move_to_notation(player: u8, pos: &Vec3i) -> String {
+    return format!("{} {}", PLAYER_LETTERS[player as usize], pos_to_notation(pos));
+}
+
+// Like move_to_notation, and "O swap" when the player took the first symbol with the pie rule
+pub fn played_to_notation(played: &Move) -> String {
+    if played.swap {
+        return format!("{} {}", PLAYER_LETTERS[played.player as usize], SWAP_WORD);
+    }
+    return move_to_notation(played.player, &played.position);
+}
+
+// The player of a swap like "O swap", None for anything else
+pub fn parse_swap(text: &str) -> Option<u8> {
+    match text.trim().split_once(' ') {
+        Some((letter, word)) if word.trim() == SWAP_WORD => {
+            return parse_player(letter).ok();
+        },
+        _ => return None,
+    }
+}
+
+// A single letter like "O", in either case
+pub fn parse_player(text: &str) -> Result<u8, String> {
+    match PLAYER_LETTERS.iter().position(|l| l.to_string() == text.trim().to_uppercase()) {
+        Some(player) => return Ok(player as u8),
+        None => return Err(format!("'{}' is not a player, expected one of {}", text, PLAYER_LETTERS.iter().collect::<String>())),
+    }
+}
+
+// Doesn't know about the board, the caller must check that the position is in the cube
+pub fn parse_pos(text: &str) -> Result<Vec3i, String> {
+    let error = || format!("'{}' is not a valid position, expected something like c2.4", text);
+
+    let mut chars = text.trim().chars();
+    let x = match chars.next() {
+        Some(column) if column.is_ascii_lowercase() => column as i32 - 'a' as i32,
+        _ => return Err(error()),
+    };
+
+    let rest = chars.as_str();
+    let (y, z) = match rest.split_once('.') {
+        Some((y, z)) => (parse_coordinate(y).ok_or_else(error)?, parse_coordinate(z).ok_or_else(error)?),
+        None => return Err(error()),
+    };
+
+    return Ok(vec3i(x, y, z));
+}
+
+// Returns the player and the position
+pub fn parse_move(text: &str) -> Result<(u8, Vec3i), String> {
+    let (letter, pos) = match text.trim().split_once(' ') {
+        Some(parts) => parts,
+        None => return Err(format!("'{}' is not a valid move, expected something like X c2.4", text)),
+    };
+
+    return Ok((parse_player(letter)?, parse_pos(pos)?));
+}
+
+// 1-based in the text, 0-based in the result
+fn parse_coordinate(text: &str) -> Option<i32> {
+    match text.parse::<i32>() {
+        Ok(number) if number >= 1 => return Some(number - 1),
+        _ => return None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_round_trip() {
+        for x in 0..10 {
+            for y in 0..10 {
+                for z in 0..10 {
+                    let pos = vec3i(x, y, z);
+                    let text = pos_to_notation(&pos);
+                    assert_eq!(parse_pos(&text), Ok(pos), "{}", text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn moves_round_trip() {
+        for player in 0..PLAYER_LETTERS.len() as u8 {
+            let pos = vec3i(2, 1, 3);
+            let text = move_to_notation(player, &pos);
+            assert_eq!(parse_move(&text), Ok((player, pos)), "{}", text);
+        }
+    }
+
+    #[test]
+    fn notation_matches_the_example() {
+        assert_eq!(move_to_notation(0, &vec3i(2, 1, 3)), "X c2.4");
+        assert_eq!(move_to_notation(1, &vec3i(0, 9, 0)), "O a10.1");
+        assert_eq!(parse_move(" o j10.10 "), Ok((1, vec3i(9, 9, 9))));
+    }
+
+    #[test]
+    fn invalid_notation_is_rejected() {
+        for text in ["", "X", "Xc2.4", "Q c2.4", "X c2", "X C2.4", "X c0.4", "X c2.0", "X c2.-1", "X 2.4", "X c.4", "X c2.4.5"] {
+            assert!(parse_move(text).is_err(), "'{}' was accepted", text);
+        }
+    }
+}
@@ -0,0 +1,417 @@
+use crate::board::*;
+use crate::notation;
+use crate::rules;
+use crate::rules::BlockType;
+use crate::settings::*;
+use crate::util::*;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Lets programs written by anyone play the game, one text line per message, like UCI for chess.
+// Unknown lines are ignored on both sides, so that new messages can be added later.
+// Version 2 added pie, swap and keep.
+//
+// Game to engine:
+//     protocol 2                        Always first, the version of the protocol
+//     rules size 6x6x6 wrap torus win 5 players 2 order 1,2 gravity none pie off
+//                                       Same values as the command line options of the game
+//     position X a1.1 O c2.4            Every symbol on the board, "position" alone when it's empty
+//     go O movetime 5000                Play for O, with this many milliseconds for the move
+//     pie O movetime 5000               With the pie rule, asks O whether it takes the first symbol,
+//                                       sent instead of go right after the first move
+//     quit                              The engine should exit
+//
+// Engine to game:
+//     name Some Bot                     Optional, before ready
+//     ready                             Answer to protocol, the engine can receive the rules
+//     info any text                     Optional, logged by the game
+//     move c2.4                         Answer to go, the chosen block (the symbol still falls with gravity)
+//     swap                              Answer to pie, the first symbol becomes the one of the engine
+//     keep                              Answer to pie, the engine is then asked for a move
+//
+// The engine is started for every move, so it never has to follow a game.
+
+pub const PROTOCOL_VERSION: u32 = 2;
+pub const DEFAULT_MOVE_TIME_MS: u32 = 5000;
+const GRACE_TIME_MS: u64 = 2000; // Starting the process and reading the pipes isn't free
+const POLL_INTERVAL: Duration = Duration::from_millis(20); // How often a waiting game checks if it was cancelled
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExternalConfig {
+    pub command: String, // Program and its arguments, separated by spaces
+    pub move_time_ms: u32,
+}
+
+pub enum GameMessage {
+    Protocol(u32),
+    Rules(GameSettings),
+    Position(Vec<(u8, Vec3i)>), // Player and block of every symbol
+    Go { player: u8, move_time_ms: u32 },
+    Pie { player: u8, move_time_ms: u32 },
+    Quit,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum EngineMessage {
+    Name(String),
+    Ready,
+    Info(String),
+    Move(Vec3i),
+    Swap,
+    Keep,
+}
+
+// "engine:<command>", like "engine:./dummy-engine --seed 3"
+pub fn parse_external_config(text: &str) -> Result<ExternalConfig, String> {
+    match text.strip_prefix("engine:") {
+        Some(command) if !command.trim().is_empty() => return Ok(ExternalConfig { command: command.trim().to_string(), move_time_ms: DEFAULT_MOVE_TIME_MS }),
+        _ => return Err(format!("'{}' is not a valid engine, expected something like engine:./dummy-engine", text)),
+    }
+}
+
+// Milliseconds given to an external engine for each move, like the value of --movetime
+pub fn parse_move_time(text: &str) -> Result<u32, String> {
+    match text.trim().parse::<u32>() {
+        Ok(move_time_ms) if move_time_ms > 0 => return Ok(move_time_ms),
+        _ => return Err(format!("'{}' is not a valid move time, expected a number of milliseconds like {}", text, DEFAULT_MOVE_TIME_MS)),
+    }
+}
+
+pub fn format_rules(settings: &GameSettings) -> String {
+    let order: Vec<String> = settings.turn_order.iter().map(|player| (player + 1).to_string()).collect();
+    return format!("rules size {} wrap {} win {} players {} order {} gravity {} pie {}",
+        board_config_to_string(&settings.board),
+        wrap_to_string(&settings.board.wrap),
+        settings.count_to_win,
+        settings.players.len(),
+        order.join(","),
+        placement_to_string(&settings.placement),
+        switch_to_string(settings.pie_rule));
+}
+
+pub fn format_position(board: &Board) -> String {
+    let mut res = "position".to_string();
+    for (id, block) in board.blocks.iter().enumerate() {
+        match block {
+            BlockType::Player(player) => res += &format!(" {}", notation::move_to_notation(*player, &id_to_pos(id as i32, &board.config))),
+            BlockType::None => {},
+        }
+    }
+
+    return res;
+}
+
+// The time is the budget of this move alone, there is no clock across the game
+pub fn format_go(player: u8, move_time_ms: u32) -> String {
+    return format!("go {} movetime {}", notation::PLAYER_LETTERS[player as usize], move_time_ms);
+}
+
+// Same budget as format_go, the answer counts as a move
+pub fn format_pie(player: u8, move_time_ms: u32) -> String {
+    return format!("pie {} movetime {}", notation::PLAYER_LETTERS[player as usize], move_time_ms);
+}
+
+// None for the messages this version doesn't know
+pub fn parse_game_message(line: &str) -> Result<Option<GameMessage>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let invalid = || format!("Invalid message '{}'", line);
+
+    match words.first() {
+        Some(&"protocol") => {
+            let version = words.get(1).and_then(|version| version.parse::<u32>().ok()).ok_or_else(invalid)?;
+            return Ok(Some(GameMessage::Protocol(version)));
+        },
+        Some(&"rules") => return Ok(Some(GameMessage::Rules(parse_rules(&words[1..])?))),
+        Some(&"position") => {
+            let mut symbols = Vec::new();
+            for pair in words[1..].chunks(2) {
+                symbols.push(notation::parse_move(&pair.join(" "))?);
+            }
+            return Ok(Some(GameMessage::Position(symbols)));
+        },
+        Some(&"go") | Some(&"pie") => {
+            if words.len() != 4 || words[2] != "movetime" {
+                return Err(invalid());
+            }
+            let player = notation::parse_player(words[1])?;
+            let move_time_ms = words[3].parse::<u32>().map_err(|_| invalid())?;
+            if words[0] == "pie" {
+                return Ok(Some(GameMessage::Pie { player, move_time_ms }));
+            }
+            return Ok(Some(GameMessage::Go { player, move_time_ms }));
+        },
+        Some(&"quit") => return Ok(Some(GameMessage::Quit)),
+        _ => return Ok(None),
+    }
+}
+
+// None for the messages this version doesn't know
+pub fn parse_engine_message(line: &str) -> Result<Option<EngineMessage>, String> {
+    let (name, rest) = match line.trim().split_once(' ') {
+        Some((name, rest)) => (name, rest.trim()),
+        None => (line.trim(), ""),
+    };
+
+    match name {
+        "name" => return Ok(Some(EngineMessage::Name(rest.to_string()))),
+        "ready" => return Ok(Some(EngineMessage::Ready)),
+        "info" => return Ok(Some(EngineMessage::Info(rest.to_string()))),
+        "move" => return Ok(Some(EngineMessage::Move(notation::parse_pos(rest)?))),
+        "swap" => return Ok(Some(EngineMessage::Swap)),
+        "keep" => return Ok(Some(EngineMessage::Keep)),
+        _ => return Ok(None),
+    }
+}
+
+// Pairs of "name value", in any order
+fn parse_rules(words: &[&str]) -> Result<GameSettings, String> {
+    let mut settings = default_settings();
+    let mut wrap = None;
+
+    for pair in words.chunks(2) {
+        let value = match pair.get(1) {
+            Some(value) => *value,
+            None => return Err(format!("Missing value after '{}' in the rules", pair[0])),
+        };
+
+        match pair[0] {
+            "size" => settings.board = parse_board_config(value)?,
+            "wrap" => wrap = Some(parse_wrap(value)?),
+            "win" => settings.count_to_win = value.parse::<i32>().map_err(|_| format!("'{}' is not a valid win length", value))?,
+            "players" => {
+                let count = value.parse::<usize>().map_err(|_| format!("'{}' is not a valid number of players", value))?;
+                if !(MIN_PLAYER_COUNT..=MAX_PLAYER_COUNT).contains(&count) {
+                    return Err(format!("Invalid number of players: {}", count));
+                }
+                settings.players = get_player_presets(count);
+            },
+            "order" => {
+                let mut order = Vec::new();
+                for part in value.split(',') {
+                    match part.parse::<u8>() {
+                        Ok(number) if number >= 1 => order.push(number - 1),
+                        _ => return Err(format!("'{}' is not a valid turn order", value)),
+                    }
+                }
+                settings.turn_order = order;
+            },
+            "gravity" => settings.placement = parse_placement(value)?,
+            "pie" => settings.pie_rule = parse_switch("pie", value)?,
+            _ => {}, // Rules added by later versions
+        }
+    }
+
+    match wrap {
+        Some(wrap) => settings.board.wrap = wrap,
+        None => {},
+    }
+
+    check_settings(&settings)?;
+    return Ok(settings);
+}
+
+// Starts the program, asks for one move and closes it. None if it fails, is too slow or is cancelled.
+pub fn run_external_engine(board: &Board, player: u8, config: &ExternalConfig, settings: &GameSettings, cancelled: &AtomicBool) -> Option<Vec3i> {
+    match run_external_session(board, config, settings, &format_go(player, config.move_time_ms), cancelled) {
+        Some(EngineMessage::Move(pos)) => {
+            // The game must not trust the engine
+            if !is_in_cube(&pos, &board.config) || rules::get_landing_pos(&pos, board, settings).is_none() {
+                println!("ERR: The engine '{}' chose {}, which can't be played", config.command, notation::pos_to_notation(&pos));
+                return None;
+            }
+            return Some(pos);
+        },
+        Some(_) => {
+            println!("ERR: The engine '{}' didn't answer go with a move", config.command);
+            return None;
+        },
+        None => return None,
+    }
+}
+
+// Same for the pie rule, whether the engine takes the first symbol. An engine that fails keeps its side.
+pub fn run_external_swap(board: &Board, player: u8, config: &ExternalConfig, settings: &GameSettings, cancelled: &AtomicBool) -> bool {
+    match run_external_session(board, config, settings, &format_pie(player, config.move_time_ms), cancelled) {
+        Some(EngineMessage::Swap) => return true,
+        Some(EngineMessage::Keep) => return false,
+        Some(_) => {
+            println!("ERR: The engine '{}' didn't answer pie with swap or keep", config.command);
+            return false;
+        },
+        None => return false,
+    }
+}
+
+// Sends the position and `question`, returns the answer (not a name, ready or info message)
+fn run_external_session(board: &Board, config: &ExternalConfig, settings: &GameSettings, question: &str, cancelled: &AtomicBool) -> Option<EngineMessage> {
+    let mut parts = config.command.split_whitespace();
+    let program = parts.next().unwrap_or("");
+    let mut child = match Command::new(program).args(parts).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            println!("ERR: Couldn't start the engine '{}': {}", config.command, err);
+            return None;
+        },
+    };
+
+    let mut input = child.stdin.take().expect("The input of the engine is piped");
+    let output = child.stdout.take().expect("The output of the engine is piped");
+
+    // Read on another thread, so that the game can give up on an engine that doesn't answer
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines() {
+            match line {
+                Ok(line) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                },
+                Err(_) => break,
+            }
+        }
+    });
+
+    let res = ask_engine(&mut input, &receiver, board, config, settings, question, cancelled);
+
+    let _ = writeln!(input, "quit");
+    drop(input);
+    let _ = child.kill(); // It had its chance to exit on its own
+    let _ = child.wait();
+
+    match res {
+        Ok(answer) => return Some(answer),
+        Err(msg) => {
+            if !cancelled.load(Ordering::Relaxed) {
+                println!("ERR: The engine '{}' failed: {}", config.command, msg);
+            }
+            return None;
+        },
+    }
+}
+
+fn ask_engine(input: &mut impl Write, receiver: &mpsc::Receiver<String>, board: &Board, config: &ExternalConfig, settings: &GameSettings, question: &str, cancelled: &AtomicBool) -> Result<EngineMessage, String> {
+    let start_deadline = Instant::now() + Duration::from_millis(GRACE_TIME_MS);
+    send_line(input, &format!("protocol {}", PROTOCOL_VERSION))?;
+    let mut name = config.command.clone();
+    loop {
+        match receive_message(receiver, start_deadline, cancelled)? {
+            EngineMessage::Name(engine_name) => name = engine_name,
+            EngineMessage::Ready => break,
+            EngineMessage::Info(text) => println!("LOG: {}: {}", name, text),
+            EngineMessage::Move(_) | EngineMessage::Swap | EngineMessage::Keep => return Err("it answered before ready".to_string()),
+        }
+    }
+
+    send_line(input, &format_rules(settings))?;
+    send_line(input, &format_position(board))?;
+    send_line(input, question)?;
+
+    let deadline = Instant::now() + Duration::from_millis(config.move_time_ms as u64 + GRACE_TIME_MS);
+    loop {
+        match receive_message(receiver, deadline, cancelled)? {
+            EngineMessage::Info(text) => println!("LOG: {}: {}", name, text),
+            EngineMessage::Name(_) | EngineMessage::Ready => {},
+            answer => return Ok(answer),
+        }
+    }
+}
+
+fn send_line(input: &mut impl Write, line: &str) -> Result<(), String> {
+    writeln!(input, "{}", line).and_then(|_| input.flush()).map_err(|err| format!("couldn't send '{}': {}", line, err))?;
+    return Ok(());
+}
+
+// Skips the lines it doesn't know, fails when the deadline passes
+fn receive_message(receiver: &mpsc::Receiver<String>, deadline: Instant, cancelled: &AtomicBool) -> Result<EngineMessage, String> {
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err("cancelled".to_string());
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Err("no answer in time".to_string());
+        }
+
+        match receiver.recv_timeout(POLL_INTERVAL.min(deadline - now)) {
+            Ok(line) => {
+                match parse_engine_message(&line)? {
+                    Some(message) => return Ok(message),
+                    None => {},
+                }
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {},
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err("it stopped".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_round_trip() {
+        let mut settings = default_settings();
+        settings.board = BoardConfig { size: [4, 5, 6], wrap: [true, false, true] };
+        settings.count_to_win = 4;
+        settings.players = get_player_presets(3);
+        settings.turn_order = vec![2, 0, 1];
+        settings.placement = parse_placement("-y").unwrap();
+
+        let line = format_rules(&settings);
+        match parse_game_message(&line) {
+            Ok(Some(GameMessage::Rules(parsed))) => assert_eq!(format_rules(&parsed), line),
+            _ => panic!("Couldn't read back '{}'", line),
+        }
+    }
+
+    #[test]
+    fn position_lists_every_symbol() {
+        let settings = default_settings();
+        let mut board = new_board(&settings.board);
+        assert_eq!(format_position(&board), "position");
+
+        set_block(&vec3i(0, 0, 0), BlockType::Player(0), &mut board);
+        set_block(&vec3i(2, 1, 3), BlockType::Player(1), &mut board);
+
+        match parse_game_message(&format_position(&board)) {
+            Ok(Some(GameMessage::Position(symbols))) => assert_eq!(symbols, vec![(0, vec3i(0, 0, 0)), (1, vec3i(2, 1, 3))]),
+            _ => panic!("Couldn't read back the position"),
+        }
+    }
+
+    #[test]
+    fn pie_is_a_question_apart_from_go() {
+        match parse_game_message(&format_pie(1, 300)) {
+            Ok(Some(GameMessage::Pie { player, move_time_ms })) => assert_eq!((player, move_time_ms), (1, 300)),
+            _ => panic!("Couldn't read back the pie question"),
+        }
+        assert!(matches!(parse_game_message(&format_go(1, 300)), Ok(Some(GameMessage::Go { .. }))));
+        assert!(parse_game_message("go O time 300").is_err());
+        assert!(parse_game_message("go Q movetime 300").is_err());
+    }
+
+    #[test]
+    fn move_times_are_positive_milliseconds() {
+        assert_eq!(parse_move_time("250"), Ok(250));
+        for text in ["0", "-5", "1.5", "fast", ""] {
+            assert!(parse_move_time(text).is_err(), "'{}' was accepted", text);
+        }
+    }
+
+    #[test]
+    fn engine_messages() {
+        assert_eq!(parse_engine_message("move c2.4"), Ok(Some(EngineMessage::Move(vec3i(2, 1, 3)))));
+        assert_eq!(parse_engine_message("name Some Bot"), Ok(Some(EngineMessage::Name("Some Bot".to_string()))));
+        assert_eq!(parse_engine_message("ready"), Ok(Some(EngineMessage::Ready)));
+        assert_eq!(parse_engine_message("swap"), Ok(Some(EngineMessage::Swap)));
+        assert_eq!(parse_engine_message("something new"), Ok(None));
+        assert!(parse_engine_message("move c2").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+extern crate serde;
+extern crate serde_json;
+
+use crate::board::*;
+use crate::notation;
+use crate::rules;
+use crate::rules::{BlockType, GameState, Move};
+use crate::settings::*;
+use crate::util::*;
+use serde::{Deserialize, Serialize};
+
+// Increase when the format changes, and keep a way to read the older versions in `parse_record`
+pub const SAVE_VERSION: u32 = 3;
+pub const DEFAULT_SAVE_PATH: &str = "./save.json";
+
+// Everything needed to restore a game
+#[derive(Serialize, Deserialize)]
+pub struct GameRecord {
+    pub version: u32,
+    pub settings: SettingsRecord,
+    pub moves: Vec<MoveRecord>,
+    pub current_turn: Option<u8>, // None if the game is over
+    pub cube_rotation: [f32; 4],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SettingsRecord {
+    pub size: [i32; 3],
+    pub wrap: String,
+    pub count_to_win: i32,
+    pub player_count: usize,
+    pub turn_order: Vec<u8>,
+    pub gravity: String,
+    #[serde(default)] // Added in version 3, older games were played without it
+    pub pie_rule: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MoveRecord {
+    #[serde(rename = "move")]
+    pub notation: String, // Like "X c2.4"
+    pub time: f32,
+}
+
+// Version 1 stored the moves as numbers
+#[derive(Deserialize)]
+pub struct GameRecordV1 {
+    pub settings: SettingsRecord,
+    pub moves: Vec<MoveRecordV1>,
+    pub current_turn: Option<u8>,
+    pub cube_rotation: [f32; 4],
+}
+
+#[derive(Deserialize)]
+pub struct MoveRecordV1 {
+    pub player: u8,
+    pub position: [i32; 3],
+    pub time: f32,
+}
+
+// The rotation only matters to the window, the other front ends save the identity
+pub fn create_record(game: &rules::Game, cube_rotation: [f32; 4]) -> GameRecord {
+    let settings = &game.settings;
+
+    return GameRecord {
+        version: SAVE_VERSION,
+        settings: SettingsRecord {
+            size: settings.board.size,
+            wrap: wrap_to_string(&settings.board.wrap),
+            count_to_win: settings.count_to_win,
+            player_count: settings.players.len(),
+            turn_order: settings.turn_order.clone(),
+            gravity: placement_to_string(&settings.placement),
+            pie_rule: settings.pie_rule,
+        },
+        moves: game.history.iter().map(|played| MoveRecord {
+            notation: notation::played_to_notation(played),
+            time: played.time,
+        }).collect(),
+        current_turn: match game.state {
+            GameState::Turn(player) => Some(player),
+            _ => None,
+        },
+        cube_rotation,
+    };
+}
+
+pub fn write_record(path: &str, record: &GameRecord) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(record).map_err(|err| err.to_string())?;
+    std::fs::write(path, content).map_err(|err| format!("Couldn't write '{}': {}", path, err))?;
+
+    return Ok(());
+}
+
+pub fn read_record(path: &str) -> Result<GameRecord, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| format!("Couldn't read '{}': {}", path, err))?;
+    return parse_record(&content);
+}
+
+// Reads the version first, so that older files can be converted
+pub fn parse_record(content: &str) -> Result<GameRecord, String> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|err| format!("Not a valid save file: {}", err))?;
+
+    let version = match value.get("version").and_then(|version| version.as_u64()) {
+        Some(version) => version as u32,
+        None => return Err("Not a valid save file: no version".to_string()),
+    };
+
+    let invalid = |err: serde_json::Error| format!("Not a valid save file: {}", err);
+    match version {
+        1 => {
+            let old: GameRecordV1 = serde_json::from_value(value).map_err(invalid)?;
+            return Ok(GameRecord {
+                version: SAVE_VERSION,
+                settings: old.settings,
+                moves: old.moves.iter().map(convert_move_v1).collect::<Result<Vec<MoveRecord>, String>>()?,
+                current_turn: old.current_turn,
+                cube_rotation: old.cube_rotation,
+            });
+        },
+        2 | 3 => return serde_json::from_value(value).map_err(invalid), // Version 3 added the pie rule and swaps
+        _ => return Err(format!("This save file has version {}, this game only knows up to version {}", version, SAVE_VERSION)),
+    }
+}
+
+// The notation only has letters for the known players and columns, the rest is checked with the moves
+fn convert_move_v1(played: &MoveRecordV1) -> Result<MoveRecord, String> {
+    let pos = vec3i_arr(played.position);
+    if played.player as usize >= notation::PLAYER_LETTERS.len() || pos.x < 0 || pos.x >= 26 || pos.y < 0 || pos.z < 0 {
+        return Err(format!("Not a valid save file: player {} can't play at {:?}", played.player, played.position));
+    }
+
+    return Ok(MoveRecord {
+        notation: notation::move_to_notation(played.player, &pos),
+        time: played.time,
+    });
+}
+
+pub fn record_to_settings(record: &SettingsRecord) -> Result<GameSettings, String> {
+    if record.player_count < MIN_PLAYER_COUNT || record.player_count > MAX_PLAYER_COUNT {
+        return Err(format!("Invalid number of players: {}", record.player_count));
+    }
+
+    let settings = GameSettings {
+        board: BoardConfig { size: record.size, wrap: parse_wrap(&record.wrap)? },
+        count_to_win: record.count_to_win,
+        players: get_player_presets(record.player_count),
+        turn_order: record.turn_order.clone(),
+        placement: parse_placement(&record.gravity)?,
+        pie_rule: record.pie_rule,
+        book: None,
+    };
+
+    check_settings(&settings)?;
+    return Ok(settings);
+}
+
+// Checks the moves of the record against the rules, without playing them
+pub fn get_record_moves(record: &GameRecord, settings: &GameSettings) -> Result<Vec<Move>, String> {
+    let mut board = new_board(&settings.board);
+    let mut moves: Vec<Move> = Vec::with_capacity(record.moves.len());
+    let mut turn = Some(settings.turn_order[0]);
+    for played in &record.moves {
+        let swap = notation::parse_swap(&played.notation);
+        let (player, position) = match (swap, moves.first()) {
+            // Same conditions as rules::can_swap
+            (Some(player), Some(first)) if moves.len() == 1 && settings.pie_rule && settings.players.len() == 2 => (player, first.position.clone()),
+            (Some(_), _) => return Err(format!("Invalid save file: {} is only allowed right after the first move, with the pie rule", played.notation)),
+            (None, _) => notation::parse_move(&played.notation).map_err(|err| format!("Invalid save file: {}", err))?,
+        };
+
+        if turn != Some(player) {
+            return Err(format!("Invalid save file: {} played out of turn", played.notation));
+        }
+        if swap.is_none() && (!is_in_cube(&position, &settings.board) || rules::get_landing_pos(&position, &board, settings) != Some(position.clone())) {
+            return Err(format!("Invalid save file: {} can't be played", played.notation));
+        }
+
+        set_block(&position, BlockType::Player(player), &mut board);
+        let game_over = !rules::check_for_victory_at(&position, &board, settings).is_empty() || !rules::is_win_still_possible(&board, settings);
+        turn = if game_over { None } else { Some(rules::get_next_player(player, settings)) };
+
+        moves.push(Move {
+            player,
+            position,
+            time: played.time,
+            swap: swap.is_some(),
+        });
+    }
+
+    if turn != record.current_turn {
+        return Err("Invalid save file: the current turn doesn't match the moves".to_string());
+    }
+
+    return Ok(moves);
+}
+
+// Builds the game of the record, with the controllers and the opening book of `current` since they are not saved
+pub fn record_to_game(record: &GameRecord, current: &GameSettings) -> Result<rules::Game, String> {
+    let mut settings = record_to_settings(&record.settings)?;
+    for (player, current_player) in settings.players.iter_mut().zip(&current.players) {
+        player.controller = current_player.controller.clone();
+    }
+    settings.book = current.book.clone();
+
+    let moves = get_record_moves(record, &settings)?;
+
+    let mut game = rules::new_game(settings);
+    for played in moves {
+        rules::play_move(played, &mut game);
+    }
+
+    return Ok(game);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_games_load_back() {
+        let mut game = rules::new_game(default_settings());
+        rules::play_at(&vec3i(0, 0, 0), 1.0, &mut game);
+        rules::play_at(&vec3i(2, 3, 4), 2.5, &mut game);
+
+        let content = serde_json::to_string(&create_record(&game, [0.0, 0.0, 0.0, 1.0])).unwrap();
+        let loaded = record_to_game(&parse_record(&content).unwrap(), &game.settings).unwrap();
+
+        assert_eq!(loaded.history.len(), 2);
+        assert_eq!(loaded.history[1].position, vec3i(2, 3, 4));
+        assert_eq!(rules::get_current_player(&loaded), rules::get_current_player(&game));
+    }
+
+    #[test]
+    fn swaps_are_saved_with_the_pie_rule_only() {
+        let mut settings = default_settings();
+        settings.pie_rule = true;
+        let mut game = rules::new_game(settings);
+        rules::play_at(&vec3i(1, 1, 1), 1.0, &mut game);
+        rules::swap_sides(2.0, &mut game);
+
+        let mut record = parse_record(&serde_json::to_string(&create_record(&game, [0.0, 0.0, 0.0, 1.0])).unwrap()).unwrap();
+        assert_eq!(record.moves[1].notation, "O swap");
+        let loaded = record_to_game(&record, &game.settings).unwrap();
+        assert!(loaded.history[1].swap);
+        assert_eq!(get_block(&vec3i(1, 1, 1), &loaded.board), BlockType::Player(1));
+
+        record.settings.pie_rule = false;
+        assert!(record_to_game(&record, &game.settings).is_err());
+    }
+
+    #[test]
+    fn version_1_saves_are_converted() {
+        let content = r#"{"version": 1, "settings": {"size": [4, 4, 4], "wrap": "bounded", "count_to_win": 4, "player_count": 2,
+            "turn_order": [1, 0], "gravity": "none"}, "moves": [{"player": 1, "position": [2, 1, 3], "time": 1.5},
+            {"player": 0, "position": [0, 0, 0], "time": 3.0}], "current_turn": 1, "cube_rotation": [0.0, 0.0, 0.0, 1.0]}"#;
+        let record = parse_record(content).unwrap();
+        assert_eq!(record.version, SAVE_VERSION);
+        assert_eq!(record.moves.iter().map(|played| played.notation.as_str()).collect::<Vec<&str>>(), vec!["O c2.4", "X a1.1"]);
+        assert_eq!(record.moves[0].time, 1.5);
+
+        let loaded = record_to_game(&record, &default_settings()).unwrap();
+        assert_eq!(get_block(&vec3i(2, 1, 3), &loaded.board), BlockType::Player(1));
+
+        for bad_move in [r#"{"player": 4, "position": [0, 0, 0], "time": 0.0}"#, r#"{"player": 0, "position": [-1, 0, 0], "time": 0.0}"#] {
+            let content = content.replace(r#"{"player": 0, "position": [0, 0, 0], "time": 3.0}"#, bad_move);
+            assert!(parse_record(&content).err().expect("The move was accepted").starts_with("Not a valid save file"));
+        }
+    }
+
+    #[test]
+    fn moves_out_of_turn_are_refused() {
+        let content = r#"{"version": 2, "settings": {"size": [6, 6, 6], "wrap": "torus", "count_to_win": 5, "player_count": 2,
+            "turn_order": [0, 1], "gravity": "none"}, "moves": [{"move": "O a1.1", "time": 0.0}], "current_turn": 0,
+            "cube_rotation": [0.0, 0.0, 0.0, 1.0]}"#;
+        let record = parse_record(content).unwrap();
+        assert!(record_to_game(&record, &default_settings()).is_err());
+    }
+}
@@ -0,0 +1,28 @@
+// Small xorshift generator, good enough for the engines and reproducible from a seed
+pub struct Rng {
+    pub state: u64,
+}
+
+pub fn new_rng(seed: u64) -> Rng {
+    // Zero would stay zero forever
+    return Rng { state: seed.max(1) };
+}
+
+// Seeded from the clock, for games that shouldn't repeat
+pub fn rng_from_time() -> Rng {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(1);
+    return new_rng(nanos);
+}
+
+impl Rng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        return self.state;
+    }
+
+    pub fn below(&mut self, max: usize) -> usize {
+        return (self.next_u64() % max as u64) as usize;
+    }
+}
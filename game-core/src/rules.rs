@@ -0,0 +1,622 @@
+use crate::bitboard;
+use crate::board::*;
+use crate::settings::{get_gravity_axis, GameSettings, PlacementMode};
+use crate::util::*;
+
+// Directions of all possible lines, opposite directions are not repeated
+pub const LINE_DIRECTIONS: [[i32; 3]; 13] = [
+    [1, 0, 0],
+    [1, 1, 0],
+    [1, 1, 1],
+    [1, -1, 1],
+    [1, -1, -1],
+    [1, 1, -1],
+    [1, 0, -1],
+    [1, -1, 0],
+    [0, 1, 0],
+    [0, 0, 1],
+    [0, 1, 1],
+    [0, -1, 1],
+    [1, 0, 1],
+];
+
+// A game without any display, the window and the terminal both play through it
+pub struct Game {
+    pub settings: GameSettings,
+    pub board: Board,
+    pub bits: bitboard::BitBoard, // Same symbols as `board`, for the fast win tests
+    pub line_masks: bitboard::LineMasks,
+    pub history: Vec<Move>, // Moves that have been played, the last one is the most recent
+    pub redo_stack: Vec<Move>, // Undone moves, the last one is the next to be redone
+    pub state: GameState,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BlockType {
+    Player(u8), None // Id of the player in the settings
+}
+
+#[derive(Clone)]
+pub struct Move {
+    pub player: u8,
+    pub position: Vec3i, // Where the symbol landed
+    pub time: f32, // When the move was played, in seconds since the start of the game
+    pub swap: bool, // Pie rule: the player took the first symbol as its own instead of placing one, at the same position
+}
+
+#[derive(Clone)]
+pub enum GameState {
+    Turn(u8), GameWon(Vec<VictoryInfo>), Draw, // Every line completed by the winning move
+}
+
+pub fn new_game(settings: GameSettings) -> Game {
+    return Game {
+        board: new_board(&settings.board),
+        bits: bitboard::new_bitboard(),
+        line_masks: bitboard::build_line_masks(&settings.board, settings.count_to_win),
+        history: Vec::new(),
+        redo_stack: Vec::new(),
+        state: GameState::Turn(settings.turn_order[0]),
+        settings,
+    };
+}
+
+// Where a symbol ends up when the player chooses `pos`, None if it can't be placed there
+pub fn get_landing_pos(pos: &Vec3i, board: &Board, settings: &GameSettings) -> Option<Vec3i> {
+    match &settings.placement {
+        PlacementMode::Free => {
+            if get_block(pos, board) == BlockType::None {
+                return Some(pos.clone());
+            }
+            return None;
+        },
+        PlacementMode::Gravity(direction) => {
+            // Go up the column, starting from the bottom
+            let axis = get_gravity_axis(direction);
+            let row_count = board.config.size[axis];
+            let falls_to_end = vec3i_to_arr(direction)[axis] > 0;
+
+            let mut current = vec3i_to_arr(pos);
+            for i in 0..row_count {
+                current[axis] = if falls_to_end { row_count - 1 - i } else { i };
+
+                let current_pos = vec3i_arr(current);
+                if get_block(&current_pos, board) == BlockType::None {
+                    return Some(current_pos);
+                }
+            }
+
+            return None; // The column is full
+        },
+    }
+}
+
+// Plays for the current player at the chosen block, returns the move or None if the symbol can't go there
+pub fn play_at(chosen_pos: &Vec3i, time: f32, game: &mut Game) -> Option<Move> {
+    let player = match game.state {
+        GameState::Turn(player) => player,
+        GameState::GameWon(_) | GameState::Draw => return None,
+    };
+
+    let position = get_landing_pos(chosen_pos, &game.board, &game.settings)?;
+
+    // A new move makes the undone ones meaningless
+    game.redo_stack.clear();
+
+    let played = Move { player, position, time, swap: false };
+    play_move(played.clone(), game);
+    return Some(played);
+}
+
+// With the pie rule, the second player can answer the first move by making it its own: the symbol changes
+// to the one of the second player, and the first player plays again. Whoever starts has no reason to play too strong a move.
+pub fn can_swap(game: &Game) -> bool {
+    return game.settings.pie_rule
+        && game.settings.players.len() == 2
+        && game.history.len() == 1
+        && matches!(game.state, GameState::Turn(_));
+}
+
+// Swaps for the current player, returns the move or None if the pie rule doesn't allow it now
+pub fn swap_sides(time: f32, game: &mut Game) -> Option<Move> {
+    if !can_swap(game) {
+        return None;
+    }
+    let player = match game.state {
+        GameState::Turn(player) => player,
+        GameState::GameWon(_) | GameState::Draw => return None,
+    };
+
+    game.redo_stack.clear();
+
+    let played = Move { player, position: game.history[0].position.clone(), time, swap: true };
+    play_move(played.clone(), game);
+    return Some(played);
+}
+
+// Places the symbol and updates the game state, the move must be valid
+pub fn play_move(played: Move, game: &mut Game) {
+    let pos = &played.position;
+    let id = pos_to_id(pos, &game.board.config) as usize;
+    match get_block(pos, &game.board) {
+        BlockType::Player(owner) => bitboard::unmake_move(id, owner, &mut game.bits), // Only when swapping
+        BlockType::None => {},
+    }
+    set_block(pos, BlockType::Player(played.player), &mut game.board);
+    bitboard::make_move(id, played.player, &mut game.bits);
+
+    if bitboard::is_win_at(id, played.player, &game.bits, &game.line_masks) {
+        game.state = GameState::GameWon(check_for_victory_at(pos, &game.board, &game.settings));
+    }
+    else if !bitboard::is_win_still_possible(&game.bits, &game.line_masks) {
+        game.state = GameState::Draw;
+    }
+    else {
+        game.state = GameState::Turn(get_next_player(played.player, &game.settings));
+    }
+
+    game.history.push(played);
+}
+
+// Takes back the last move, returns it or None if there is none
+pub fn undo(game: &mut Game) -> Option<Move> {
+    let undone = game.history.pop()?;
+
+    // A swap gives the symbol back to the player of the move it took
+    let id = pos_to_id(&undone.position, &game.board.config) as usize;
+    bitboard::unmake_move(id, undone.player, &mut game.bits);
+    let previous_owner = if undone.swap { game.history.iter().rev().find(|played| played.position == undone.position) } else { None };
+    match previous_owner {
+        Some(played) => {
+            set_block(&undone.position, BlockType::Player(played.player), &mut game.board);
+            bitboard::make_move(id, played.player, &mut game.bits);
+        },
+        None => set_block(&undone.position, BlockType::None, &mut game.board),
+    }
+
+    // Nobody could have won or drawn before this move, so it was simply the turn of its player
+    game.state = GameState::Turn(undone.player);
+
+    game.redo_stack.push(undone.clone());
+    return Some(undone);
+}
+
+// Plays again the last undone move, returns it or None if there is none
+pub fn redo(game: &mut Game) -> Option<Move> {
+    let redone = game.redo_stack.pop()?;
+    play_move(redone.clone(), game);
+    return Some(redone);
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct VictoryInfo {
+    pub winner: BlockType,
+    pub position: Vec3i,
+    pub direction: Vec3i,
+}
+
+// Scans the whole cube, returns every line ordered by x, y, z then direction
+pub fn check_for_victory(board: &Board, settings: &GameSettings) -> Vec<VictoryInfo> {
+    let size = &board.config.size;
+    let mut res = Vec::new();
+
+    for x in 0..size[0] {
+        for y in 0..size[1] {
+            for z in 0..size[2] {
+                let block_type = get_block(&vec3i(x, y, z), board);
+
+                if block_type == BlockType::None {
+                    continue;
+                }
+
+                for delta in LINE_DIRECTIONS {
+                    // The line would go through the same block twice
+                    if get_line_period(&vec3i_arr(delta), &board.config) < settings.count_to_win {
+                        continue;
+                    }
+
+                    let mut okay = true;
+                    for i in 0..settings.count_to_win {
+                        if get_block(&vec3i(x + delta[0] * i, y + delta[1] * i, z + delta[2] * i), board) != block_type {
+                            okay = false;
+                            break;
+                        }
+                    }
+
+                    if okay {
+                        add_line(VictoryInfo {
+                            winner: block_type,
+                            position: vec3i(x, y, z),
+                            direction: vec3i_arr(delta),
+                        }, &mut res, board, settings);
+                    }
+                }
+            }
+        }
+    }
+
+    return res;
+}
+
+// Only looks at the lines that go through `pos`, usually the last placed block.
+// If there was no line before placing it, the result is the same as check_for_victory.
+pub fn check_for_victory_at(pos: &Vec3i, board: &Board, settings: &GameSettings) -> Vec<VictoryInfo> {
+    let block_type = get_block(pos, board);
+    if block_type == BlockType::None {
+        return Vec::new();
+    }
+
+    let mut found = Vec::new();
+
+    for (j, delta) in LINE_DIRECTIONS.into_iter().enumerate() {
+        if get_line_period(&vec3i_arr(delta), &board.config) < settings.count_to_win {
+            continue;
+        }
+
+        // Try every line of this direction that contains pos
+        for offset in 0..settings.count_to_win {
+            let start = wrap_pos(&vec3i(pos.x - delta[0] * offset, pos.y - delta[1] * offset, pos.z - delta[2] * offset), &board.config);
+            if !is_in_cube(&start, &board.config) {
+                continue;
+            }
+
+            let mut okay = true;
+            for i in 0..settings.count_to_win {
+                if get_block(&vec3i(start.x + delta[0] * i, start.y + delta[1] * i, start.z + delta[2] * i), board) != block_type {
+                    okay = false;
+                    break;
+                }
+            }
+
+            if okay {
+                found.push(([start.x, start.y, start.z, j as i32], VictoryInfo {
+                    winner: block_type,
+                    position: start,
+                    direction: vec3i_arr(delta),
+                }));
+            }
+        }
+    }
+
+    // Same order as check_for_victory
+    found.sort_by_key(|(key, _)| *key);
+
+    let mut res = Vec::new();
+    for (_, info) in found {
+        add_line(info, &mut res, board, settings);
+    }
+
+    return res;
+}
+
+// Ids of the blocks of the line, sorted
+pub fn get_line_block_ids(info: &VictoryInfo, board: &Board, settings: &GameSettings) -> Vec<i32> {
+    let mut res: Vec<i32> = (0..settings.count_to_win).map(|i| {
+        let pos = vec3i(
+            info.position.x + info.direction.x * i,
+            info.position.y + info.direction.y * i,
+            info.position.z + info.direction.z * i,
+        );
+        return pos_to_id(&pos, &board.config);
+    }).collect();
+
+    res.sort();
+    return res;
+}
+
+// When a line goes all around the cube, it can be found from every one of its blocks: only keep the first one
+fn add_line(info: VictoryInfo, lines: &mut Vec<VictoryInfo>, board: &Board, settings: &GameSettings) {
+    let ids = get_line_block_ids(&info, board, settings);
+    if lines.iter().any(|other| get_line_block_ids(other, board, settings) == ids) {
+        return;
+    }
+
+    lines.push(info);
+}
+
+// Is there still a line that contains the symbols of only one player (or none)?
+// When the cube is full, there is none left.
+pub fn is_win_still_possible(board: &Board, settings: &GameSettings) -> bool {
+    let size = &board.config.size;
+
+    for x in 0..size[0] {
+        for y in 0..size[1] {
+            for z in 0..size[2] {
+                for delta in LINE_DIRECTIONS {
+                    if get_line_period(&vec3i_arr(delta), &board.config) < settings.count_to_win {
+                        continue;
+                    }
+
+                    let mut owner = BlockType::None;
+                    let mut possible = true;
+                    for i in 0..settings.count_to_win {
+                        let pos = vec3i(x + delta[0] * i, y + delta[1] * i, z + delta[2] * i);
+                        if pos_to_id(&pos, &board.config) < 0 { // Falls outside of the cube
+                            possible = false;
+                            break;
+                        }
+
+                        let block_type = get_block(&pos, board);
+                        if block_type == BlockType::None {
+                            continue;
+                        }
+
+                        if owner == BlockType::None {
+                            owner = block_type;
+                        }
+                        else if owner != block_type {
+                            possible = false;
+                            break;
+                        }
+                    }
+
+                    if possible {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+
+    return false;
+}
+
+pub fn is_board_full(board: &Board) -> bool {
+    return board.blocks.iter().all(|block| *block != BlockType::None);
+}
+
+// Follows the turn order of the settings
+pub fn get_next_player(player: u8, settings: &GameSettings) -> u8 {
+    let index = settings.turn_order.iter().position(|id| *id == player).expect("Player is not in the turn order");
+    return settings.turn_order[(index + 1) % settings.turn_order.len()];
+}
+
+pub fn get_current_player(game: &Game) -> BlockType {
+    match &game.state {
+        GameState::Turn(player) => return BlockType::Player(*player),
+        GameState::GameWon(lines) => return lines[0].winner,
+        GameState::Draw => return BlockType::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::*;
+    use crate::settings::*;
+    use crate::test_util::{settings_with, test_settings};
+
+    // Plays random moves until someone wins, checking both methods after every move
+    #[test]
+    fn incremental_check_matches_full_scan_during_games() {
+        let mut rng = new_rng(0x2545F4914F6CDD1D);
+
+        for settings in test_settings() {
+            for _ in 0..30 {
+                let mut board = new_board(&settings.board);
+                let mut free: Vec<i32> = (0..board.blocks.len() as i32).collect();
+                let mut turn = 0;
+
+                while !free.is_empty() {
+                    let id = free.swap_remove(rng.below(free.len()));
+                    let pos = id_to_pos(id, &board.config);
+                    set_block(&pos, BlockType::Player((turn % 3) as u8), &mut board);
+                    turn += 1;
+
+                    let full = check_for_victory(&board, &settings);
+                    let incremental = check_for_victory_at(&pos, &board, &settings);
+                    assert_eq!(full, incremental);
+
+                    if !full.is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_completed_line_is_reported() {
+        let settings = GameSettings {
+            board: BoardConfig { size: [4, 4, 4], wrap: [false, false, false] },
+            count_to_win: 3,
+            ..default_settings()
+        };
+
+        let mut board = new_board(&settings.board);
+        for pos in [vec3i(1, 0, 0), vec3i(2, 0, 0), vec3i(0, 1, 0), vec3i(0, 2, 0), vec3i(1, 1, 1), vec3i(2, 2, 2)] {
+            set_block(&pos, BlockType::Player(0), &mut board);
+        }
+
+        let pos = vec3i(0, 0, 0);
+        set_block(&pos, BlockType::Player(0), &mut board);
+
+        assert_eq!(check_for_victory_at(&pos, &board, &settings).len(), 3);
+        assert_eq!(check_for_victory(&board, &settings), check_for_victory_at(&pos, &board, &settings));
+    }
+
+    // On boards that may already contain lines, every line found must be real and go through the block
+    #[test]
+    fn incremental_check_finds_real_lines_on_random_boards() {
+        let mut rng = new_rng(0x9E3779B97F4A7C15);
+
+        for settings in test_settings() {
+            for _ in 0..20 {
+                let mut board = new_board(&settings.board);
+                for block in board.blocks.iter_mut() {
+                    *block = [BlockType::Player(0), BlockType::Player(1), BlockType::Player(2), BlockType::None][rng.below(4)];
+                }
+
+                let full = check_for_victory(&board, &settings);
+
+                for id in 0..board.blocks.len() as i32 {
+                    let pos = id_to_pos(id, &board.config);
+                    let incremental = check_for_victory_at(&pos, &board, &settings);
+
+                    for info in incremental {
+                        assert!(full.contains(&info));
+                        assert_eq!(info.winner, get_block(&pos, &board));
+
+                        let ids = get_line_block_ids(&info, &board, &settings);
+                        assert!(ids.contains(&id));
+                        for block_id in ids {
+                            assert_eq!(board.blocks[block_id as usize], info.winner);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn play_all(moves: &[[i32; 3]], game: &mut Game) {
+        for pos in moves {
+            assert!(play_at(&vec3i_arr(*pos), 0.0, game).is_some(), "{:?} couldn't be played", pos);
+        }
+    }
+
+    #[test]
+    fn symbols_go_on_empty_blocks_only() {
+        let mut game = new_game(settings_with("4", "torus", 4, 2));
+
+        let played = play_at(&vec3i(1, 2, 3), 1.5, &mut game).expect("The cube is empty");
+        assert_eq!(played.player, 0);
+        assert_eq!(played.position, vec3i(1, 2, 3));
+        assert_eq!(get_block(&vec3i(1, 2, 3), &game.board), BlockType::Player(0));
+
+        // Taken, and nothing changes
+        assert!(play_at(&vec3i(1, 2, 3), 2.0, &mut game).is_none());
+        assert_eq!(game.history.len(), 1);
+        assert!(matches!(game.state, GameState::Turn(1)));
+    }
+
+    #[test]
+    fn pie_rule_gives_the_first_symbol_to_the_second_player() {
+        let mut settings = settings_with("4", "torus", 4, 2);
+        assert!(swap_sides(0.0, &mut new_game(settings.clone())).is_none());
+
+        settings.pie_rule = true;
+        let mut game = new_game(settings);
+        assert!(!can_swap(&game));
+        play_all(&[[1, 2, 3]], &mut game);
+        assert!(can_swap(&game));
+
+        let swapped = swap_sides(1.0, &mut game).expect("Right after the first move");
+        assert!(swapped.swap && swapped.player == 1);
+        assert_eq!(get_block(&vec3i(1, 2, 3), &game.board), BlockType::Player(1));
+        assert!(bitboard::has_bit(pos_to_id(&vec3i(1, 2, 3), &game.board.config) as usize, &game.bits.players[1]));
+        assert!(matches!(game.state, GameState::Turn(0)));
+        assert!(!can_swap(&game));
+
+        // Undone, the symbol is the one of the first move again
+        undo(&mut game);
+        assert_eq!(get_block(&vec3i(1, 2, 3), &game.board), BlockType::Player(0));
+        assert_eq!(game.bits, bitboard::board_to_bitboard(&game.board));
+        assert!(matches!(game.state, GameState::Turn(1)));
+        assert!(redo(&mut game).is_some_and(|redone| redone.swap));
+        assert_eq!(game.bits, bitboard::board_to_bitboard(&game.board));
+    }
+
+    #[test]
+    fn symbols_fall_with_gravity() {
+        let mut settings = settings_with("4", "bounded", 4, 2);
+        settings.placement = parse_placement("-y").unwrap();
+        let mut game = new_game(settings);
+
+        assert_eq!(play_at(&vec3i(2, 3, 1), 0.0, &mut game).map(|played| played.position), Some(vec3i(2, 0, 1)));
+        assert_eq!(play_at(&vec3i(2, 0, 1), 0.0, &mut game).map(|played| played.position), Some(vec3i(2, 1, 1)));
+        play_all(&[[2, 0, 1], [2, 0, 1]], &mut game);
+
+        // The column is full
+        assert!(play_at(&vec3i(2, 2, 1), 0.0, &mut game).is_none());
+
+        let mut settings = settings_with("4", "bounded", 4, 2);
+        settings.placement = parse_placement("+x").unwrap();
+        assert_eq!(get_landing_pos(&vec3i(0, 1, 1), &new_board(&settings.board), &settings), Some(vec3i(3, 1, 1)));
+    }
+
+    #[test]
+    fn turns_follow_the_turn_order() {
+        let mut settings = settings_with("5", "torus", 5, 3);
+        settings.turn_order = vec![2, 0, 1];
+        let mut game = new_game(settings);
+
+        let mut players = Vec::new();
+        for i in 0..6 {
+            players.push(play_at(&vec3i(i % 5, i / 5, 0), 0.0, &mut game).expect("").player);
+        }
+        assert_eq!(players, vec![2, 0, 1, 2, 0, 1]);
+
+        // Undo gives the turn back, redo plays the same move again
+        assert_eq!(undo(&mut game).map(|undone| undone.player), Some(1));
+        assert!(matches!(game.state, GameState::Turn(1)));
+        assert_eq!(redo(&mut game).map(|redone| redone.position), Some(vec3i(0, 1, 0)));
+        assert!(matches!(game.state, GameState::Turn(2)));
+
+        // A new move forgets the undone ones
+        undo(&mut game);
+        play_at(&vec3i(4, 4, 4), 0.0, &mut game);
+        assert!(redo(&mut game).is_none());
+    }
+
+    #[test]
+    fn lines_wrap_around_the_cube() {
+        // Crosses at x = 3, 4, 0, 1 along a 5 blocks torus, the circles stay out of the way
+        let mut game = new_game(settings_with("5", "torus", 4, 2));
+        play_all(&[[3, 0, 0], [0, 2, 2], [4, 0, 0], [1, 2, 4], [0, 0, 0], [3, 3, 2]], &mut game);
+        assert!(matches!(game.state, GameState::Turn(0)));
+
+        play_all(&[[1, 0, 0]], &mut game);
+        match &game.state {
+            GameState::GameWon(lines) => {
+                assert_eq!(lines.len(), 1);
+                assert_eq!(lines[0].winner, BlockType::Player(0));
+                assert_eq!(lines[0].direction, vec3i(1, 0, 0));
+                assert_eq!(get_line_block_ids(&lines[0], &game.board, &game.settings), vec![0, 1, 3, 4]);
+            },
+            _ => panic!("The wrapped line should win"),
+        }
+
+        // Diagonals wrap too
+        let mut game = new_game(settings_with("4", "torus", 4, 2));
+        play_all(&[[2, 2, 2], [0, 1, 2], [3, 3, 3], [0, 2, 1], [0, 0, 0], [2, 0, 1], [1, 1, 1]], &mut game);
+        assert!(matches!(game.state, GameState::GameWon(_)));
+    }
+
+    #[test]
+    fn diagonals_of_the_xz_planes_win() {
+        // Every direction once, never with its opposite
+        for (i, direction) in LINE_DIRECTIONS.iter().enumerate() {
+            let opposite = direction.map(|coordinate| -coordinate);
+            assert!(!LINE_DIRECTIONS[i + 1..].contains(direction) && !LINE_DIRECTIONS.contains(&opposite), "{:?}", direction);
+        }
+
+        let mut game = new_game(settings_with("5", "bounded", 4, 2));
+        play_all(&[[0, 2, 0], [0, 0, 4], [1, 2, 1], [4, 4, 0], [2, 2, 2], [2, 4, 4]], &mut game);
+        assert!(matches!(game.state, GameState::Turn(0)));
+
+        play_all(&[[3, 2, 3]], &mut game);
+        match &game.state {
+            GameState::GameWon(lines) => {
+                assert_eq!(lines[0].winner, BlockType::Player(0));
+                assert_eq!(lines[0].direction, vec3i(1, 0, 1));
+            },
+            _ => panic!("The diagonal of the xz plane should win"),
+        }
+    }
+
+    #[test]
+    fn lines_stop_at_bounded_sides() {
+        let mut game = new_game(settings_with("5", "bounded", 4, 2));
+        play_all(&[[3, 0, 0], [0, 2, 2], [4, 0, 0], [1, 2, 4], [0, 0, 0], [3, 3, 2], [1, 0, 0]], &mut game);
+        assert!(matches!(game.state, GameState::Turn(1)));
+
+        // Only the axes listed in the topology wrap
+        let mut game = new_game(settings_with("5", "y", 4, 2));
+        play_all(&[[3, 0, 0], [0, 2, 2], [4, 0, 0], [1, 2, 4], [0, 0, 0], [3, 3, 2], [1, 0, 0]], &mut game);
+        assert!(matches!(game.state, GameState::Turn(1)));
+        play_all(&[[2, 3, 0], [4, 4, 4], [2, 4, 0], [4, 3, 4], [2, 0, 0], [4, 1, 4]], &mut game);
+        assert!(matches!(game.state, GameState::Turn(1)));
+        play_all(&[[2, 1, 0]], &mut game);
+        assert!(matches!(game.state, GameState::GameWon(_)));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::board::*;
+use crate::ai;
+use crate::book;
+use crate::mcts;
+use crate::protocol;
+use crate::record;
+use crate::util::*;
+use std::sync::Arc;
+
+pub const USAGE: &str = "\
+Usage: first-test [options]
+    --size <n | XxYxZ>    Size of the cube, between 3 and 10 blocks per side (default: 6)
+    --wrap <topology>     torus, bounded, or the axes along which lines wrap, like xz (default: torus)
+    --win <n>             Number of aligned symbols needed to win (default: 5, or less if the cube is smaller)
+    --players <n>         Number of players, between 2 and 4 (default: 2)
+    --order <list>        Order in which the players play, like 3,1,2 (default: 1,2,...)
+    --gravity <axis>      Symbols fall along this axis, like -y (default: none)
+    --pie <on | off>      Pie rule: after the first move, the second player can take it as its own (default: off)
+    --ai <player[:level]> Let the computer play for this player, level is easy, medium, hard or a search depth
+                          (default: medium), followed by :sym to merge symmetric positions, or mcts[:playouts[:exploration]] for the Monte Carlo engine
+                          (default: mcts:2000:1.4), or engine:<command> for another program, can be repeated like --ai 2 --ai 3:hard
+    --movetime <ms>       Milliseconds that the other programs of --ai get for each move (default: 5000)
+    --book <path>         Opening book played by the computer, or none (default: ./book.json when it exists)
+    --save <path>         File used by the save and load keys (default: ./save.json)
+    --load <path>         Continue a saved game, its settings replace the ones above";
+
+pub const MIN_COUNT_TO_WIN: i32 = 3;
+pub const DEFAULT_COUNT_TO_WIN: i32 = 5;
+pub const MIN_PLAYER_COUNT: usize = 2;
+pub const MAX_PLAYER_COUNT: usize = 4;
+
+pub const CROSS_COLOR: Color = [0.9, 0.2, 0.2, 1.0];
+pub const CIRCLE_COLOR: Color = [0.2, 0.2, 0.9, 1.0];
+pub const TRIANGLE_COLOR: Color = [0.2, 0.8, 0.2, 1.0];
+pub const SQUARE_COLOR: Color = [0.7, 0.2, 0.9, 1.0];
+
+pub type Color = [f32; 4]; // RGBA
+
+#[derive(Clone)]
+pub struct PlayerInfo {
+    pub name: String,
+    pub symbol: String, // Name of the texture
+    pub color: Color,
+    pub controller: Controller,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Controller {
+    Human,
+    AlphaBeta(ai::AlphaBetaConfig),
+    Mcts(mcts::MctsConfig),
+    External(protocol::ExternalConfig), // Another program, speaking the text protocol
+}
+
+#[derive(Clone, PartialEq)]
+pub enum PlacementMode {
+    Free, // Symbols are placed on the chosen block
+    Gravity(Vec3i), // Symbols fall in this direction (relative to the cube) until they reach another block
+}
+
+// What the game is started with
+pub struct LaunchOptions {
+    pub settings: GameSettings,
+    pub save_path: String,
+    pub load_path: Option<String>, // Game to continue instead of starting a new one
+}
+
+// Everything that is chosen once, when the game starts
+#[derive(Clone)]
+pub struct GameSettings {
+    pub board: BoardConfig,
+    pub count_to_win: i32,
+    pub players: Vec<PlayerInfo>,
+    pub turn_order: Vec<u8>, // Ids of the players, in the order they play
+    pub placement: PlacementMode,
+    pub pie_rule: bool, // The second player can swap sides after the first move, with 2 players
+    pub book: Option<Arc<book::OpeningBook>>, // Openings played by the engines, shared by every copy of the settings
+}
+
+pub fn default_settings() -> GameSettings {
+    return GameSettings {
+        board: cube_config(DEFAULT_ROW_COUNT),
+        count_to_win: DEFAULT_COUNT_TO_WIN,
+        players: get_player_presets(MIN_PLAYER_COUNT),
+        turn_order: (0..MIN_PLAYER_COUNT as u8).collect(),
+        placement: PlacementMode::Free,
+        pie_rule: false,
+        book: None,
+    };
+}
+
+// The first `count` players of the list: cross, circle, triangle, square
+pub fn get_player_presets(count: usize) -> Vec<PlayerInfo> {
+    let presets = [
+        ("Cross", "x.png", CROSS_COLOR),
+        ("Circle", "o.png", CIRCLE_COLOR),
+        ("Triangle", "triangle.png", TRIANGLE_COLOR),
+        ("Square", "square.png", SQUARE_COLOR),
+    ];
+
+    return presets.into_iter().take(count).map(|(name, symbol, color)| PlayerInfo {
+        name: name.to_string(),
+        symbol: symbol.to_string(),
+        color,
+        controller: Controller::Human,
+    }).collect();
+}
+
+// The tools and the tests have no book unless --book is given, so that their games don't depend on the folder they run in
+pub fn parse_args(args: &[String]) -> Result<LaunchOptions, String> {
+    return parse_args_with_book(args, None);
+}
+
+// `default_book` is read when there is no --book and the file exists
+pub fn parse_args_with_book(args: &[String], default_book: Option<&str>) -> Result<LaunchOptions, String> {
+    let mut settings = default_settings();
+    let mut save_path = record::DEFAULT_SAVE_PATH.to_string();
+    let mut load_path = None;
+    let mut count_to_win = None;
+    let mut wrap = None;
+    let mut turn_order = None;
+    let mut ai_players = Vec::new();
+    let mut book_path = None;
+    let mut move_time_ms = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let name = args[i].as_str();
+        let value = args.get(i + 1).ok_or(format!("Missing value after '{}'", name));
+
+        match name {
+            "--size" => settings.board = parse_board_config(value?)?,
+            "--wrap" => wrap = Some(parse_wrap(value?)?),
+            "--win" => count_to_win = Some(parse_number(name, value?)?),
+            "--players" => {
+                let count = parse_number(name, value?)?;
+                if count < MIN_PLAYER_COUNT as i32 || count > MAX_PLAYER_COUNT as i32 {
+                    return Err(format!("There must be between {} and {} players, got {}", MIN_PLAYER_COUNT, MAX_PLAYER_COUNT, count));
+                }
+                settings.players = get_player_presets(count as usize);
+            },
+            "--order" => turn_order = Some(parse_turn_order(value?)?),
+            "--gravity" => settings.placement = parse_placement(value?)?,
+            "--pie" => settings.pie_rule = parse_switch(name, value?)?,
+            "--ai" => ai_players.push(parse_ai_player(value?)?),
+            "--movetime" => move_time_ms = Some(protocol::parse_move_time(value?)?),
+            "--book" => book_path = Some(value?.clone()),
+            "--save" => save_path = value?.clone(),
+            "--load" => load_path = Some(value?.clone()),
+            _ => return Err(format!("Unknown option '{}'", name)),
+        }
+
+        i += 2;
+    }
+
+    match wrap {
+        Some(wrap) => settings.board.wrap = wrap,
+        None => {},
+    }
+
+    for (player, controller) in ai_players {
+        if player >= settings.players.len() {
+            return Err(format!("There is no player {}, there are only {} players", player + 1, settings.players.len()));
+        }
+        settings.players[player].controller = controller;
+    }
+
+    match move_time_ms {
+        Some(move_time_ms) => {
+            for player in settings.players.iter_mut() {
+                set_move_time(&mut player.controller, move_time_ms);
+            }
+        },
+        None => {},
+    }
+
+    settings.turn_order = match turn_order {
+        Some(order) => order,
+        None => (0..settings.players.len() as u8).collect(),
+    };
+
+    settings.count_to_win = match count_to_win {
+        Some(count) => count,
+        None => DEFAULT_COUNT_TO_WIN.min(get_max_row_count(&settings.board)),
+    };
+
+    settings.book = match book_path.as_deref().or(default_book.filter(|path| std::path::Path::new(path).exists())) {
+        Some("none") | None => None,
+        Some(path) => Some(Arc::new(book::load_book(path)?)),
+    };
+
+    check_settings(&settings)?;
+    return Ok(LaunchOptions { settings, save_path, load_path });
+}
+
+pub fn parse_switch(name: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "on" => return Ok(true),
+        "off" => return Ok(false),
+        _ => return Err(format!("'{}' is not valid for {}, expected on or off", value, name)),
+    }
+}
+
+pub fn switch_to_string(value: bool) -> String {
+    return if value { "on".to_string() } else { "off".to_string() };
+}
+
+// Makes sure the settings allow to win the game
+pub fn check_settings(settings: &GameSettings) -> Result<(), String> {
+    check_board_config(&settings.board)?;
+
+    let max_count = get_max_row_count(&settings.board);
+    if settings.count_to_win < MIN_COUNT_TO_WIN || settings.count_to_win > max_count {
+        return Err(format!("The number of symbols to align must be between {} and {} on a {} board, got {}", 
+            MIN_COUNT_TO_WIN, max_count, board_config_to_string(&settings.board), settings.count_to_win));
+    }
+
+    // Every player must play exactly once per round
+    let mut sorted_order = settings.turn_order.clone();
+    sorted_order.sort();
+    if sorted_order != (0..settings.players.len() as u8).collect::<Vec<u8>>() {
+        return Err(format!("The turn order must contain each of the {} players exactly once", settings.players.len()));
+    }
+
+    if settings.pie_rule && settings.players.len() != 2 {
+        return Err("The pie rule is only for games of 2 players".to_string());
+    }
+
+    return Ok(());
+}
+
+fn parse_number(name: &str, value: &str) -> Result<i32, String> {
+    return value.parse::<i32>().map_err(|_| format!("Expected a number after '{}', got '{}'", name, value));
+}
+
+// "none", or an axis with an optional sign: "-y" means that symbols fall down
+pub fn parse_placement(value: &str) -> Result<PlacementMode, String> {
+    let direction = match value {
+        "none" => return Ok(PlacementMode::Free),
+        "x" | "+x" => vec3i(1, 0, 0),
+        "-x" => vec3i(-1, 0, 0),
+        "y" | "+y" => vec3i(0, 1, 0),
+        "-y" => vec3i(0, -1, 0),
+        "z" | "+z" => vec3i(0, 0, 1),
+        "-z" => vec3i(0, 0, -1),
+        _ => return Err(format!("'{}' is not a valid gravity axis, expected none, or an axis like -y", value)),
+    };
+
+    return Ok(PlacementMode::Gravity(direction));
+}
+
+pub fn placement_to_string(placement: &PlacementMode) -> String {
+    match placement {
+        PlacementMode::Free => return "none".to_string(),
+        PlacementMode::Gravity(direction) => {
+            let axis = get_gravity_axis(direction);
+            let sign = if vec3i_to_arr(direction)[axis] < 0 { "-" } else { "+" };
+            return format!("{}{}", sign, ["x", "y", "z"][axis]);
+        }
+    }
+}
+
+// Index of the axis along which symbols fall
+pub fn get_gravity_axis(direction: &Vec3i) -> usize {
+    return vec3i_to_arr(direction).iter().position(|delta| *delta != 0).expect("Gravity has no direction");
+}
+
+// 1-based player number, then an optional difficulty: "2" or "2:hard"
+fn parse_ai_player(value: &str) -> Result<(usize, Controller), String> {
+    let (player, difficulty) = match value.split_once(':') {
+        Some((player, difficulty)) => (player, Some(difficulty)),
+        None => (value, None),
+    };
+
+    let player = match player.trim().parse::<usize>() {
+        Ok(number) if number >= 1 => number - 1,
+        _ => return Err(format!("'{}' is not a valid player, expected something like 2 or 2:hard", value)),
+    };
+
+    let controller = match difficulty {
+        Some(level) => parse_controller(level)?,
+        None => Controller::AlphaBeta(ai::default_alpha_beta_config()),
+    };
+
+    return Ok((player, controller));
+}
+
+// The part of --ai after the player: "hard", "4:sym", "mcts:500" or "engine:<command>"
+pub fn parse_controller(text: &str) -> Result<Controller, String> {
+    match text.trim() {
+        level if level.starts_with("mcts") => return Ok(Controller::Mcts(mcts::parse_mcts_config(level)?)),
+        level if level.starts_with("engine:") => return Ok(Controller::External(protocol::parse_external_config(level)?)),
+        level => return Ok(Controller::AlphaBeta(ai::parse_alpha_beta_config(level)?)),
+    }
+}
+
+// Only external engines have a time limit, the other ones are limited by their level
+pub fn set_move_time(controller: &mut Controller, move_time_ms: u32) {
+    match controller {
+        Controller::External(config) => config.move_time_ms = move_time_ms,
+        _ => {},
+    }
+}
+
+// 1-based player numbers separated by commas
+fn parse_turn_order(value: &str) -> Result<Vec<u8>, String> {
+    let mut res = Vec::new();
+    for part in value.split(',') {
+        match part.trim().parse::<u8>() {
+            Ok(number) if number >= 1 => res.push(number - 1),
+            _ => return Err(format!("'{}' is not a valid turn order, expected something like 3,1,2", value)),
+        }
+    }
+
+    return Ok(res);
+}
+
+// Reads the options of a front end from the command line, exits with the usage if they are wrong
+pub fn get_options_from_args() -> LaunchOptions {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match parse_args_with_book(&args, Some(book::DEFAULT_BOOK_PATH)) {
+        Ok(options) => return options,
+        Err(msg) => {
+            println!("ERR: {}", msg);
+            println!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
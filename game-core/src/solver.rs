@@ -0,0 +1,398 @@
+use crate::bitboard::{self, BitBoard, LineMasks};
+use crate::board::*;
+use crate::job;
+use crate::rules::{self, BlockType};
+use crate::settings::GameSettings;
+use crate::zobrist;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Proves who wins small cubes with perfect play, with a proof-number search:
+// the tree grows where the fewest positions are left to prove, or to disprove, the win.
+// Positions reached again through other moves, or as a symmetry of another one, are only solved once.
+
+pub const SOLUTIONS_VERSION: u32 = 1;
+pub const DEFAULT_SOLUTIONS_PATH: &str = "./solutions.json";
+pub const DEFAULT_NODE_LIMIT: usize = 2_000_000; // Largest search tree, in positions
+pub const MAX_SOLVED_BLOCK_COUNT: usize = 64; // A 4x4x4 cube, larger ones are out of reach anyway
+
+const INFINITY: u64 = u64::MAX;
+const NO_PARENT: usize = usize::MAX;
+
+// The value of a position for the player to move, with the number of moves the winner needs
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Value {
+    Win(u32),
+    Loss(u32),
+    Draw,
+}
+
+// Solved positions, by rules and symmetry-reduced board
+#[derive(Default, Serialize, Deserialize)]
+pub struct SolutionTable {
+    pub version: u32,
+    pub positions: BTreeMap<String, Value>, // Sorted, so that the file changes as little as possible
+}
+
+// The solver running on another thread, like an engine job. None if the tree grew too large.
+pub type SolverJob = job::Job<Option<Value>>;
+
+struct Node {
+    block_id: usize, // Block of the move that led here
+    parent: usize,
+    children: Vec<usize>,
+    proof: u64, // Positions left to prove that the attacker wins, 0 once proven
+    disproof: u64, // Same for the defender, 0 once the attacker can't win anymore
+    depth: u32, // Moves since the root
+    plies: u32, // Once proven, the attacker wins within this many plies from the root
+    hash: u64, // Canonical hash of the position, set when the node is expanded
+}
+
+// Only small cubes with 2 players. The search doesn't know about the pie rule, so not before the swap was possible.
+pub fn can_solve(game: &rules::Game) -> bool {
+    let settings = &game.settings;
+    return settings.players.len() == 2
+        && get_block_count(&settings.board) <= MAX_SOLVED_BLOCK_COUNT
+        && !(settings.pie_rule && game.history.len() < 2);
+}
+
+// The value of the position for `player`, who is to move. None if the search needed more than `node_limit` positions
+// or was cancelled. The game must not be over.
+pub fn solve(board: &Board, player: u8, settings: &GameSettings, node_limit: usize, cancelled: &AtomicBool) -> Option<Value> {
+    let opponent = rules::get_next_player(player, settings);
+    let masks = bitboard::build_line_masks(&board.config, settings.count_to_win);
+    let max_plies = board.blocks.iter().filter(|block| **block == BlockType::None).count() as u32;
+    if !bitboard::is_win_still_possible(&bitboard::board_to_bitboard(board), &masks) {
+        return Some(Value::Draw);
+    }
+
+    // The n-th move of the player to move is the ply 2n - 1, the opponent plays the even plies
+    let win = prove_win(board, player, player, max_plies, settings, &masks, node_limit, cancelled)?;
+    if win.proven {
+        let plies = shorten_win(board, player, player, win, settings, &masks, node_limit, cancelled);
+        return Some(Value::Win(plies.div_ceil(2)));
+    }
+
+    let loss = prove_win(board, player, opponent, max_plies, settings, &masks, node_limit.saturating_sub(win.node_count), cancelled)?;
+    if loss.proven {
+        let plies = shorten_win(board, player, opponent, loss, settings, &masks, node_limit.saturating_sub(win.node_count), cancelled);
+        return Some(Value::Loss(plies / 2));
+    }
+
+    return Some(Value::Draw);
+}
+
+// The outcome of one proof-number search
+#[derive(Clone, Copy, Debug)]
+pub struct Proof {
+    pub proven: bool,
+    pub plies: u32, // Longest line of the proof tree, the attacker wins within this many plies
+    pub node_count: usize,
+}
+
+// Looks for a win 2 plies shorter than the one of `proof`, for as long as there is one and `node_limit` allows it.
+// Returns the number of plies of the shortest win found, exact unless the nodes ran out.
+#[allow(clippy::too_many_arguments)]
+fn shorten_win(board: &Board, player: u8, attacker: u8, proof: Proof, settings: &GameSettings, masks: &LineMasks, node_limit: usize, cancelled: &AtomicBool) -> u32 {
+    let mut best = proof;
+    let mut used = proof.node_count;
+    while best.plies > 2 {
+        match prove_win(board, player, attacker, best.plies - 2, settings, masks, node_limit.saturating_sub(used), cancelled) {
+            Some(shorter) if shorter.proven => {
+                used += shorter.node_count;
+                best = shorter;
+            },
+            _ => break,
+        }
+    }
+
+    return best.plies;
+}
+
+// Can `attacker` force a win within `max_plies` moves, with `player` to move?
+// None if the tree grew past `node_limit` or the search was cancelled.
+#[allow(clippy::too_many_arguments)]
+pub fn prove_win(board: &Board, player: u8, attacker: u8, max_plies: u32, settings: &GameSettings, masks: &LineMasks, node_limit: usize, cancelled: &AtomicBool) -> Option<Proof> {
+    let mut board = board.clone();
+    let mut bits = bitboard::board_to_bitboard(&board);
+    let mut hasher = zobrist::new_hasher(&board, settings, true);
+    let mut solved: HashMap<u64, Option<u32>> = HashMap::new(); // Plies of the proven positions, None for the disproven ones
+    let opponent = rules::get_next_player(player, settings);
+    let mut nodes = vec![Node { block_id: 0, parent: NO_PARENT, children: Vec::new(), proof: 1, disproof: 1, depth: 0, plies: 0, hash: 0 }];
+
+    while nodes[0].proof != 0 && nodes[0].disproof != 0 {
+        if nodes.len() >= node_limit || cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        // Follow the children that decide the numbers of their parent, down to a leaf
+        let mut current = 0;
+        while !nodes[current].children.is_empty() {
+            let to_move = if nodes[current].depth % 2 == 0 { player } else { opponent };
+            let next = if to_move == attacker {
+                *nodes[current].children.iter().min_by_key(|child| nodes[**child].proof).expect("Checked above")
+            }
+            else {
+                *nodes[current].children.iter().min_by_key(|child| nodes[**child].disproof).expect("Checked above")
+            };
+
+            play_block(nodes[next].block_id, to_move, &mut board, &mut bits, &mut hasher);
+            current = next;
+        }
+
+        let to_move = if nodes[current].depth % 2 == 0 { player } else { opponent };
+        let hash = zobrist::get_hash(to_move, &mut hasher);
+        match solved.get(&hash) {
+            Some(Some(plies)) => {
+                nodes[current].proof = 0;
+                nodes[current].disproof = INFINITY;
+                nodes[current].plies = *plies;
+            },
+            Some(None) => {
+                nodes[current].proof = INFINITY;
+                nodes[current].disproof = 0;
+            },
+            None => {
+                nodes[current].hash = hash;
+                expand_node(current, player, opponent, attacker, max_plies, settings, masks, &mut nodes, &board, &mut bits);
+            },
+        }
+
+        // Back up to the root, taking the moves back on the way
+        loop {
+            let to_move = if nodes[current].depth % 2 == 0 { player } else { opponent };
+            update_node(current, to_move == attacker, &mut nodes);
+            let node = &nodes[current];
+            if !node.children.is_empty() && (node.proof == 0 || node.disproof == 0) {
+                solved.insert(node.hash, if node.proof == 0 { Some(node.plies) } else { None });
+            }
+
+            let parent = nodes[current].parent;
+            if parent == NO_PARENT {
+                break;
+            }
+
+            // The move into `current` was played by the player to move at its parent
+            let played_by = if nodes[parent].depth % 2 == 0 { player } else { opponent };
+            take_back_block(nodes[current].block_id, played_by, &mut board, &mut bits, &mut hasher);
+            current = parent;
+        }
+    }
+
+    let proven = nodes[0].proof == 0;
+    let plies = if proven { nodes[0].plies } else { 0 };
+    return Some(Proof { proven, plies, node_count: nodes.len() });
+}
+
+// Adds a child for every block where a symbol can land, the finished games get their final numbers right away
+#[allow(clippy::too_many_arguments)]
+fn expand_node(index: usize, player: u8, opponent: u8, attacker: u8, max_plies: u32, settings: &GameSettings, masks: &LineMasks, nodes: &mut Vec<Node>, board: &Board, bits: &mut BitBoard) {
+    let depth = nodes[index].depth + 1;
+    let to_move = if depth % 2 == 1 { player } else { opponent };
+
+    for id in 0..board.blocks.len() {
+        let pos = id_to_pos(id as i32, &board.config);
+        if rules::get_landing_pos(&pos, board, settings) != Some(pos) {
+            continue;
+        }
+
+        bitboard::make_move(id, to_move, bits);
+        let (proof, disproof) = if bitboard::is_win_at(id, to_move, bits, masks) {
+            if to_move == attacker { (0, INFINITY) } else { (INFINITY, 0) }
+        }
+        else if !bitboard::is_win_still_possible(bits, masks) || depth >= max_plies {
+            (INFINITY, 0)
+        }
+        else {
+            (1, 1)
+        };
+        bitboard::unmake_move(id, to_move, bits);
+
+        let child = nodes.len();
+        nodes[index].children.push(child);
+        nodes.push(Node { block_id: id, parent: index, children: Vec::new(), proof, disproof, depth, plies: depth, hash: 0 });
+    }
+
+    // Nowhere to play, nobody wins from here
+    if nodes[index].children.is_empty() {
+        nodes[index].proof = INFINITY;
+        nodes[index].disproof = 0;
+    }
+}
+
+// The attacker needs one child proven, the defender needs all of them disproven.
+// Once proven, the attacker takes its quickest proven child and the defender its slowest child, they are all proven.
+fn update_node(index: usize, attacker_to_move: bool, nodes: &mut [Node]) {
+    if nodes[index].children.is_empty() {
+        return;
+    }
+
+    let mut min_proof = INFINITY;
+    let mut min_disproof = INFINITY;
+    let mut sum_proof: u64 = 0;
+    let mut sum_disproof: u64 = 0;
+    for child in &nodes[index].children {
+        min_proof = min_proof.min(nodes[*child].proof);
+        min_disproof = min_disproof.min(nodes[*child].disproof);
+        sum_proof = sum_proof.saturating_add(nodes[*child].proof);
+        sum_disproof = sum_disproof.saturating_add(nodes[*child].disproof);
+    }
+
+    if attacker_to_move {
+        nodes[index].proof = min_proof;
+        nodes[index].disproof = sum_disproof;
+    }
+    else {
+        nodes[index].proof = sum_proof;
+        nodes[index].disproof = min_disproof;
+    }
+
+    if nodes[index].proof == 0 {
+        let lengths = nodes[index].children.iter().filter(|child| nodes[**child].proof == 0).map(|child| nodes[*child].plies);
+        nodes[index].plies = if attacker_to_move { lengths.min() } else { lengths.max() }.expect("A proven node has a proven child");
+    }
+}
+
+fn play_block(id: usize, player: u8, board: &mut Board, bits: &mut BitBoard, hasher: &mut zobrist::Hasher) {
+    board.blocks[id] = BlockType::Player(player);
+    bitboard::make_move(id, player, bits);
+    zobrist::toggle_block(id, player, hasher);
+}
+
+fn take_back_block(id: usize, player: u8, board: &mut Board, bits: &mut BitBoard, hasher: &mut zobrist::Hasher) {
+    board.blocks[id] = BlockType::None;
+    bitboard::unmake_move(id, player, bits);
+    zobrist::toggle_block(id, player, hasher);
+}
+
+// Like "Cross can force a win within 3 moves", the shortest win when the solver had enough nodes to find it
+pub fn describe_value(value: &Value, player: u8, settings: &GameSettings) -> String {
+    let opponent = rules::get_next_player(player, settings);
+    let moves = |count: u32| if count == 1 { "1 move".to_string() } else { format!("{} moves", count) };
+
+    match value {
+        Value::Win(count) => return format!("{} can force a win within {}", settings.players[player as usize].name, moves(*count)),
+        Value::Loss(count) => return format!("{} can force a win within {}", settings.players[opponent as usize].name, moves(*count)),
+        Value::Draw => return "Draw with perfect play".to_string(),
+    }
+}
+
+pub fn new_solution_table() -> SolutionTable {
+    return SolutionTable { version: SOLUTIONS_VERSION, positions: BTreeMap::new() };
+}
+
+pub fn lookup(board: &Board, player: u8, settings: &GameSettings, table: &SolutionTable) -> Option<Value> {
+    return table.positions.get(&zobrist::get_position_key(board, player, settings).0).copied();
+}
+
+pub fn insert(board: &Board, player: u8, value: Value, settings: &GameSettings, table: &mut SolutionTable) {
+    table.positions.insert(zobrist::get_position_key(board, player, settings).0, value);
+}
+
+// An empty table if there is no file yet
+pub fn load_solutions(path: &str) -> Result<SolutionTable, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(new_solution_table()),
+        Err(err) => return Err(format!("Couldn't read '{}': {}", path, err)),
+    };
+
+    let table: SolutionTable = serde_json::from_str(&content).map_err(|err| format!("Invalid solutions file '{}': {}", path, err))?;
+    if table.version != SOLUTIONS_VERSION {
+        return Err(format!("Solutions file '{}' has version {}, expected {}", path, table.version, SOLUTIONS_VERSION));
+    }
+    return Ok(table);
+}
+
+pub fn save_solutions(path: &str, table: &SolutionTable) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(table).map_err(|err| err.to_string())?;
+    return std::fs::write(path, content).map_err(|err| format!("Couldn't write '{}': {}", path, err));
+}
+
+pub fn start_job(board: &Board, player: u8, settings: &GameSettings, move_number: usize) -> SolverJob {
+    let board = board.clone();
+    let settings = settings.clone();
+    return job::start_job(player, move_number, move |cancelled| solve(&board, player, &settings, DEFAULT_NODE_LIMIT, cancelled));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation;
+    use crate::test_util::settings_with;
+
+    fn play(moves: &[&str], settings: &GameSettings) -> rules::Game {
+        let mut game = rules::new_game(settings.clone());
+        for text in moves {
+            rules::play_at(&notation::parse_pos(text).unwrap(), 0.0, &mut game).unwrap();
+        }
+        return game;
+    }
+
+    #[test]
+    fn small_bounded_cube_is_a_first_player_win() {
+        let settings = settings_with("3", "bounded", 3, 2);
+        let board = new_board(&settings.board);
+        let value = solve(&board, 0, &settings, DEFAULT_NODE_LIMIT, &AtomicBool::new(false));
+        assert!(matches!(value, Some(Value::Win(_))));
+    }
+
+    #[test]
+    fn proven_win_is_kept_when_the_nodes_run_out() {
+        let settings = settings_with("3", "bounded", 3, 2);
+        let board = new_board(&settings.board);
+        let masks = bitboard::build_line_masks(&board.config, settings.count_to_win);
+        let cancelled = AtomicBool::new(false);
+
+        // Just enough nodes for the first proof, none left for shorter wins
+        let proof = prove_win(&board, 0, 0, 27, &settings, &masks, DEFAULT_NODE_LIMIT, &cancelled).unwrap();
+        assert!(proof.proven);
+        let value = solve(&board, 0, &settings, proof.node_count + 1, &cancelled);
+        assert_eq!(value, Some(Value::Win(proof.plies.div_ceil(2))));
+    }
+
+    #[test]
+    fn distances_count_the_moves_of_the_winner() {
+        let settings = settings_with("3", "bounded", 3, 2);
+        let cancelled = AtomicBool::new(false);
+
+        // O didn't block the column of X
+        let game = play(&["a1.1", "c3.3", "a2.1", "b3.3"], &settings);
+        assert_eq!(solve(&game.board, 0, &settings, DEFAULT_NODE_LIMIT, &cancelled), Some(Value::Win(1)));
+
+        // X threatens a3.1 and c1.1, O can only block one of them
+        let game = play(&["a1.1", "c3.3", "a2.1", "c2.1", "b1.1"], &settings);
+        assert_eq!(solve(&game.board, 1, &settings, DEFAULT_NODE_LIMIT, &cancelled), Some(Value::Loss(1)));
+    }
+
+    #[test]
+    fn pie_rule_games_are_solved_once_the_swap_is_over() {
+        let mut settings = settings_with("3", "bounded", 3, 2);
+        assert!(can_solve(&play(&[], &settings)));
+
+        settings.pie_rule = true;
+        assert!(!can_solve(&play(&[], &settings)));
+        assert!(!can_solve(&play(&["b2.2"], &settings)));
+        assert!(can_solve(&play(&["b2.2", "a1.1"], &settings)));
+    }
+
+    #[test]
+    fn symmetric_positions_share_their_solution() {
+        let settings = settings_with("4", "torus", 3, 2);
+        let mut table = new_solution_table();
+        let game = play(&["a1.1"], &settings);
+        insert(&game.board, 1, Value::Loss(2), &settings, &mut table);
+
+        // Every block of a wrapping cube is the same
+        let other = play(&["c2.4"], &settings);
+        assert_eq!(lookup(&other.board, 1, &settings, &table), Some(Value::Loss(2)));
+        assert_eq!(lookup(&other.board, 0, &settings, &table), None);
+
+        let path = std::env::temp_dir().join("first-test-solutions.json").to_string_lossy().to_string();
+        save_solutions(&path, &table).unwrap();
+        let loaded = load_solutions(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.positions, table.positions);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::board::*;
+use crate::settings::*;
+
+// Settings shared by the tests of the modules
+
+// Like the command line: "4x5x6", "torus" or the axes that wrap, the players play in order
+pub fn settings_with(size: &str, wrap: &str, count_to_win: i32, player_count: usize) -> GameSettings {
+    let mut settings = default_settings();
+    settings.board = parse_board_config(size).unwrap();
+    settings.board.wrap = parse_wrap(wrap).unwrap();
+    settings.count_to_win = count_to_win;
+    settings.players = get_player_presets(player_count);
+    settings.turn_order = (0..player_count as u8).collect();
+    return settings;
+}
+
+// Quick to search, the first player wins
+pub fn small_settings() -> GameSettings {
+    return settings_with("3", "bounded", 3, 2);
+}
+
+// Odd sizes, partial wraps and short lines, with 3 players
+pub fn test_settings() -> Vec<GameSettings> {
+    let mut res = Vec::new();
+    for (size, wrap, count_to_win) in [
+        ("6", "torus", 5),
+        ("4", "torus", 3),
+        ("4", "torus", 4),
+        ("3", "torus", 3),
+        ("3", "bounded", 3),
+        ("4x5x6", "torus", 5),
+        ("4x5x6", "bounded", 4),
+        ("5x3x4", "xz", 4),
+        ("5x3x4", "xz", 3),
+        ("6", "y", 5),
+    ] {
+        res.push(settings_with(size, wrap, count_to_win, 3));
+    }
+
+    return res;
+}
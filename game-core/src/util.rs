@@ -0,0 +1,18 @@
+#[derive(Clone, PartialEq, Debug)]
+pub struct Vec3i {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+} 
+
+pub fn vec3i(x: i32, y: i32, z: i32) -> Vec3i {
+    return Vec3i { x, y, z };
+}
+
+pub fn vec3i_arr(coords: [i32; 3]) -> Vec3i {
+    return Vec3i { x: coords[0], y: coords[1], z: coords[2] };
+}
+
+pub fn vec3i_to_arr(vec: &Vec3i) -> [i32; 3] {
+    return [vec.x, vec.y, vec.z];
+}
@@ -0,0 +1,380 @@
+use crate::board::*;
+use crate::notation;
+use crate::protocol;
+use crate::rules::BlockType;
+use crate::rng::*;
+use crate::settings::{GameSettings, PlacementMode, MAX_PLAYER_COUNT};
+use crate::util::*;
+
+pub const ZOBRIST_SEED: u64 = 0x9e3779b97f4a7c15; // Fixed, so that hashes are the same from one run to the next
+pub const MAX_SYMMETRY_ENTRIES: usize = 1 << 22; // Past this, torus translations are left out to save memory
+
+// One random key per block and player, and one per player to move
+pub struct ZobristKeys {
+    pub blocks: Vec<[u64; MAX_PLAYER_COUNT]>,
+    pub turn: [u64; MAX_PLAYER_COUNT],
+}
+
+// Hash of the position as seen through each symmetry of the cube, updated with every move
+pub struct Hasher {
+    pub keys: ZobristKeys,
+    pub symmetries: Vec<Vec<u16>>, // Where each block goes, the first one is the identity
+    pub inverses: Vec<Vec<u16>>, // Where each block comes from, for each symmetry
+    pub hashes: Vec<u64>, // One per symmetry
+    pub canonical: usize, // Index of the smallest hash
+    pub pending: Vec<(usize, u8)>, // Blocks toggled since the hashes were last needed
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Bound {
+    Exact,
+    Lower, // The real score is at least this one
+    Upper, // The real score is at most this one
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TableEntry {
+    pub hash: u64,
+    pub depth: i32,
+    pub score: i64,
+    pub bound: Bound,
+    pub best_move: Option<u16>, // Block id, as seen through the canonical symmetry
+}
+
+pub struct TranspositionTable {
+    pub entries: Vec<Option<TableEntry>>,
+}
+
+pub fn build_zobrist_keys(block_count: usize) -> ZobristKeys {
+    let mut rng = new_rng(ZOBRIST_SEED);
+    let mut blocks = Vec::with_capacity(block_count);
+    for _ in 0..block_count {
+        blocks.push([rng.next_u64(), rng.next_u64(), rng.next_u64(), rng.next_u64()]);
+    }
+
+    return ZobristKeys { blocks, turn: [rng.next_u64(), rng.next_u64(), rng.next_u64(), rng.next_u64()] };
+}
+
+// Rotations and reflections that keep the cube and its gravity the same,
+// combined with the translations along the axes that wrap when there aren't too many of them
+pub fn get_symmetries(config: &BoardConfig, placement: &PlacementMode) -> Vec<Vec<u16>> {
+    let size = config.size;
+    let gravity = match placement {
+        PlacementMode::Free => None,
+        PlacementMode::Gravity(direction) => Some(vec3i_to_arr(direction)),
+    };
+
+    // Axis permutations and sign flips: 24 rotations and 24 reflections
+    let mut point_symmetries = Vec::new();
+    for axes in [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]] {
+        if (0..3).any(|i| size[axes[i]] != size[i] || config.wrap[axes[i]] != config.wrap[i]) {
+            continue;
+        }
+
+        for flips in 0..8 {
+            let flipped = [flips & 1 != 0, flips & 2 != 0, flips & 4 != 0];
+            let keeps_gravity = match gravity {
+                Some(direction) => (0..3).all(|i| direction[axes[i]] * if flipped[i] { -1 } else { 1 } == direction[i]),
+                None => true,
+            };
+            if keeps_gravity {
+                point_symmetries.push((axes, flipped));
+            }
+        }
+    }
+
+    // Symbols can't be moved along the gravity axis, they would float
+    let mut shift_counts = [1, 1, 1];
+    for axis in 0..3 {
+        let is_gravity_axis = match gravity {
+            Some(direction) => direction[axis] != 0,
+            None => false,
+        };
+        if config.wrap[axis] && !is_gravity_axis {
+            shift_counts[axis] = size[axis];
+        }
+    }
+
+    let block_count = get_block_count(config);
+    let translation_count = (shift_counts[0] * shift_counts[1] * shift_counts[2]) as usize;
+    if point_symmetries.len() * translation_count * block_count > MAX_SYMMETRY_ENTRIES {
+        shift_counts = [1, 1, 1];
+    }
+
+    let mut res = Vec::new();
+    for (axes, flipped) in &point_symmetries {
+        for sx in 0..shift_counts[0] {
+            for sy in 0..shift_counts[1] {
+                for sz in 0..shift_counts[2] {
+                    let shift = [sx, sy, sz];
+                    let map: Vec<u16> = (0..block_count).map(|id| {
+                        let pos = vec3i_to_arr(&id_to_pos(id as i32, config));
+                        let mut mapped = [0; 3];
+                        for i in 0..3 {
+                            let value = if flipped[i] { size[i] - 1 - pos[axes[i]] } else { pos[axes[i]] };
+                            mapped[i] = (value + shift[i]) % size[i];
+                        }
+                        return pos_to_id(&vec3i_arr(mapped), config) as u16;
+                    }).collect();
+
+                    res.push(map);
+                }
+            }
+        }
+    }
+
+    // Sides are at least 3 blocks long, so no two of these are the same.
+    // The identity comes first, with no flip and no shift.
+    return res;
+}
+
+// The board as seen through the symmetry that gives the smallest text, one letter per block ('.' when empty).
+// Equivalent boards give the same text, the index of the symmetry is returned too.
+pub fn get_canonical_board(board: &Board, symmetries: &[Vec<u16>]) -> (String, usize) {
+    let mut best: Option<(Vec<u8>, usize)> = None;
+    for (index, map) in symmetries.iter().enumerate() {
+        let text = get_board_text(board, map);
+        let smaller = match &best {
+            Some((best_text, _)) => text < *best_text,
+            None => true,
+        };
+        if smaller {
+            best = Some((text, index));
+        }
+    }
+
+    let (text, index) = best.expect("There is always the identity");
+    return (String::from_utf8(text).expect("Letters are ASCII"), index);
+}
+
+// The board seen through one symmetry
+pub fn get_board_text(board: &Board, map: &[u16]) -> Vec<u8> {
+    let mut text = vec![b'.'; board.blocks.len()];
+    for (id, block) in board.blocks.iter().enumerate() {
+        match block {
+            BlockType::Player(player) => text[map[id] as usize] = notation::PLAYER_LETTERS[*player as usize] as u8,
+            BlockType::None => {},
+        }
+    }
+    return text;
+}
+
+// A key that is the same for equivalent positions under the same rules: the rules, the canonical board and the player to move.
+// Also returns the symmetry that leads to the canonical board, to store moves as seen through it.
+pub fn get_position_key(board: &Board, player: u8, settings: &GameSettings) -> (String, Vec<u16>) {
+    let mut symmetries = get_symmetries(&board.config, &settings.placement);
+    let (canonical, index) = get_canonical_board(board, &symmetries);
+    let key = format!("{} / {} {}", protocol::format_rules(settings), canonical, notation::PLAYER_LETTERS[player as usize]);
+    return (key, symmetries.swap_remove(index));
+}
+
+pub fn new_hasher(board: &Board, settings: &GameSettings, use_symmetries: bool) -> Hasher {
+    let block_count = board.blocks.len();
+    let symmetries = if use_symmetries {
+        get_symmetries(&board.config, &settings.placement)
+    }
+    else {
+        vec![(0..block_count as u16).collect()]
+    };
+
+    let inverses = symmetries.iter().map(|map| {
+        let mut inverse = vec![0; block_count];
+        for (id, image) in map.iter().enumerate() {
+            inverse[*image as usize] = id as u16;
+        }
+        return inverse;
+    }).collect();
+
+    let mut hasher = Hasher {
+        keys: build_zobrist_keys(block_count),
+        hashes: vec![0; symmetries.len()],
+        symmetries,
+        inverses,
+        canonical: 0,
+        pending: Vec::new(),
+    };
+
+    for (id, block) in board.blocks.iter().enumerate() {
+        match block {
+            BlockType::Player(player) => toggle_block(id, *player, &mut hasher),
+            BlockType::None => {},
+        }
+    }
+    update_hashes(&mut hasher);
+
+    return hasher;
+}
+
+// Adds the symbol if it wasn't there, removes it otherwise.
+// Nothing is computed until the hash is needed: the moves tried at the leaves of a search are taken back
+// before that, and a move followed by its take back costs nothing.
+pub fn toggle_block(id: usize, player: u8, hasher: &mut Hasher) {
+    if hasher.pending.last() == Some(&(id, player)) {
+        hasher.pending.pop();
+    }
+    else {
+        hasher.pending.push((id, player));
+    }
+}
+
+// Applies the pending toggles to the hash of every symmetry, then finds the smallest one
+pub fn update_hashes(hasher: &mut Hasher) {
+    if hasher.pending.is_empty() {
+        return;
+    }
+
+    for (id, player) in hasher.pending.drain(..) {
+        for (hash, map) in hasher.hashes.iter_mut().zip(&hasher.symmetries) {
+            *hash ^= hasher.keys.blocks[map[id] as usize][player as usize];
+        }
+    }
+
+    let mut best = 0;
+    for (i, hash) in hasher.hashes.iter().enumerate() {
+        if *hash < hasher.hashes[best] {
+            best = i;
+        }
+    }
+    hasher.canonical = best;
+}
+
+pub fn get_hash(player_to_move: u8, hasher: &mut Hasher) -> u64 {
+    update_hashes(hasher);
+    return hasher.hashes[hasher.canonical] ^ hasher.keys.turn[player_to_move as usize];
+}
+
+// Moves are stored as seen through the canonical symmetry, so that equivalent positions can share them
+pub fn to_canonical_move(id: usize, hasher: &mut Hasher) -> u16 {
+    update_hashes(hasher);
+    return hasher.symmetries[hasher.canonical][id];
+}
+
+pub fn from_canonical_move(canonical: u16, hasher: &mut Hasher) -> usize {
+    update_hashes(hasher);
+    return hasher.inverses[hasher.canonical][canonical as usize] as usize;
+}
+
+// `size_log2` is the number of bits of the hash used as an index
+pub fn new_transposition_table(size_log2: u32) -> TranspositionTable {
+    return TranspositionTable { entries: vec![None; 1 << size_log2] };
+}
+
+pub fn probe(hash: u64, table: &TranspositionTable) -> Option<TableEntry> {
+    let index = hash as usize & (table.entries.len() - 1);
+    match table.entries[index] {
+        Some(entry) if entry.hash == hash => return Some(entry),
+        _ => return None,
+    }
+}
+
+// Replaces the previous entry unless it comes from a deeper search of the same position
+pub fn store(entry: TableEntry, table: &mut TranspositionTable) {
+    let index = entry.hash as usize & (table.entries.len() - 1);
+    match table.entries[index] {
+        Some(previous) if previous.hash == entry.hash && previous.depth > entry.depth => {},
+        _ => table.entries[index] = Some(entry),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::parse_placement;
+    use crate::test_util::settings_with;
+
+    #[test]
+    fn symmetry_counts() {
+        assert_eq!(get_symmetries(&settings_with("4", "bounded", 3, 2).board, &PlacementMode::Free).len(), 48);
+        assert_eq!(get_symmetries(&settings_with("4x4x5", "bounded", 3, 2).board, &PlacementMode::Free).len(), 16);
+        assert_eq!(get_symmetries(&settings_with("4x5x6", "bounded", 3, 2).board, &PlacementMode::Free).len(), 8);
+        assert_eq!(get_symmetries(&settings_with("4", "torus", 3, 2).board, &PlacementMode::Free).len(), 48 * 64);
+
+        // Gravity keeps the rotations around its axis and the reflections along the others, and no shift along it
+        let gravity = GameSettings { placement: parse_placement("-y").unwrap(), ..settings_with("4", "torus", 3, 2) };
+        assert_eq!(get_symmetries(&gravity.board, &gravity.placement).len(), 8 * 16);
+
+        // Too big to list every translation
+        assert_eq!(get_symmetries(&settings_with("10", "torus", 3, 2).board, &PlacementMode::Free).len(), 48);
+    }
+
+    #[test]
+    fn symmetries_keep_winning_lines() {
+        for settings in [settings_with("4", "torus", 3, 2), settings_with("5x5x3", "xy", 3, 2), GameSettings { placement: parse_placement("-z").unwrap(), ..settings_with("4", "bounded", 3, 2) }] {
+            let mut lines: Vec<Vec<usize>> = crate::bitboard::get_winning_lines(&settings.board, 4).into_iter().map(|mut line| { line.sort(); line }).collect();
+            lines.sort();
+
+            for map in get_symmetries(&settings.board, &settings.placement) {
+                let mut mapped: Vec<Vec<usize>> = lines.iter().map(|line| {
+                    let mut res: Vec<usize> = line.iter().map(|id| map[*id] as usize).collect();
+                    res.sort();
+                    return res;
+                }).collect();
+                mapped.sort();
+                assert_eq!(mapped, lines);
+            }
+        }
+    }
+
+    #[test]
+    fn equivalent_positions_share_their_hash() {
+        let settings = settings_with("5x5x3", "xy", 3, 2);
+        let mut board = new_board(&settings.board);
+        set_block(&vec3i(0, 1, 2), BlockType::Player(0), &mut board);
+        set_block(&vec3i(4, 3, 0), BlockType::Player(1), &mut board);
+        let mut hasher = new_hasher(&board, &settings, true);
+
+        for map in hasher.symmetries.clone() {
+            let mut other = new_board(&settings.board);
+            for (id, block) in board.blocks.iter().enumerate() {
+                other.blocks[map[id] as usize] = *block;
+            }
+
+            let mut other_hasher = new_hasher(&other, &settings, true);
+            assert_eq!(get_hash(0, &mut other_hasher), get_hash(0, &mut hasher));
+            assert_ne!(get_hash(1, &mut other_hasher), get_hash(0, &mut hasher));
+        }
+
+        for id in 0..board.blocks.len() {
+            let canonical = to_canonical_move(id, &mut hasher);
+            assert_eq!(from_canonical_move(canonical, &mut hasher), id);
+        }
+    }
+
+    #[test]
+    fn incremental_hash_matches_a_full_one() {
+        let settings = settings_with("5", "bounded", 3, 2);
+        let mut board = new_board(&settings.board);
+        let mut hasher = new_hasher(&board, &settings, true);
+        let mut rng = new_rng(3);
+
+        for turn in 0..40 {
+            let id = rng.below(board.blocks.len());
+            if board.blocks[id] != BlockType::None {
+                continue;
+            }
+            let player = (turn % 2) as u8;
+            board.blocks[id] = BlockType::Player(player);
+            toggle_block(id, player, &mut hasher);
+
+            // A move and its take back cancel out
+            toggle_block(0, 1, &mut hasher);
+            toggle_block(0, 1, &mut hasher);
+
+            update_hashes(&mut hasher);
+            let full = new_hasher(&board, &settings, true);
+            assert_eq!(hasher.hashes, full.hashes);
+            assert_eq!(hasher.canonical, full.canonical);
+            assert_eq!(hasher.hashes.iter().min(), Some(&hasher.hashes[hasher.canonical]));
+        }
+    }
+
+    #[test]
+    fn table_keeps_deeper_entries() {
+        let mut table = new_transposition_table(4);
+        let entry = TableEntry { hash: 42, depth: 3, score: 10, bound: Bound::Exact, best_move: Some(7) };
+        store(entry, &mut table);
+        store(TableEntry { depth: 1, score: -5, ..entry }, &mut table);
+        assert_eq!(probe(42, &table).map(|found| found.score), Some(10));
+        assert!(probe(42 + 16, &table).is_none());
+    }
+}
@@ -1,27 +1,45 @@
-
 use crate::*;
 use crate::state::State;
 use crate::util::Vec3i;
 use crate::movement::Movement;
+use crate::settings::{Controller, GameSettings};
+
+// The rules live in game-core, the rest of the window uses them through this module
+pub use game_core::rules::{get_landing_pos, BlockType, GameState, Move, VictoryInfo};
+
 
 pub const BASE_CUBE_SIZE: f32 = 2.0;
 
-pub const CROSS_COLOR: Vec4 = [0.9, 0.2, 0.2, 1.0];
-pub const CIRCLE_COLOR: Vec4 = [0.2, 0.2, 0.9, 1.0];
+pub const NO_PLAYER_COLOR: Vec4 = [0.8, 0.8, 0.8, 1.0];
+pub const FLOOR_COLOR: Vec4 = [0.9, 0.6, 0.2, 1.0];
 pub const HIGHLIGHT_COLOR: Vec4 = [1.0, 1.0, 0.6, 1.0];
 pub const HIGHLIGHT_SPEED: f32 = 0.8 * 6.29;
-pub const ROW_COUNT: i32 = 6;
-pub const COUNT_TO_WIN: i32 = 5;
+pub const HINT_WIN_COLOR: Vec4 = [0.2, 1.0, 0.3, 1.0];
+pub const HINT_BLOCK_COLOR: Vec4 = [1.0, 0.3, 0.1, 1.0];
+pub const HINT_OPEN_FOUR_COLOR: Vec4 = [1.0, 0.9, 0.1, 1.0];
 pub const ROTATE_SPEED_DECREASE: f32 = 5.0;
 pub const CUBE_POS: [f32; 3] = [0.0, 0.0, -5.0];
 pub const BG_SCALE: f32 = 10.0;
+pub const AI_MOVE_DELAY: f32 = 0.5; // Seconds before the computer plays, so that its moves can be followed
 
 pub struct GameInfo {
     pub cube_transform_matrix: Mat4,
     pub cube_rotation: Quat,
     pub cube_size: f32,
-    pub blocks: [game::BlockType; (ROW_COUNT * ROW_COUNT * ROW_COUNT) as usize],
+    pub rules: rules::Game, // Board, history and turn, shared with the other front ends
     pub last_block_id: i32,
+    pub save_path: String,
+    pub turn_start_time: f32,
+    pub engine_job: Option<engine::EngineJob>, // Engine thinking about the current turn
+    pub show_hints: bool,
+    pub hints: Vec<hints::Hint>,
+    pub hints_move_number: Option<usize>, // Length of the history when the hints were found
+    pub show_solution: bool,
+    pub solutions: solver::SolutionTable, // Positions solved so far, saved when the window closes
+    pub solutions_changed: bool,
+    pub solver_job: Option<solver::SolverJob>,
+    pub solution: Option<solver::Value>, // None while solving, or when the position is out of reach
+    pub solution_move_number: Option<usize>, // Length of the history when the solution was asked for
     pub cube_rotation_velocity: Quat,
     pub cube_release_rotation: Quat,
     pub cube_release_time: f32,
@@ -35,21 +53,9 @@ pub struct GameInfo {
     pub depth: i32,
 
     pub cube_size_mov: Movement<f32>, 
-
-    pub state: GameState,
-}
-
-#[derive(Clone, Copy, PartialEq)]
-pub enum BlockType {
-    Cross, Circle, None
-}
-
-#[derive(Clone)]
-pub enum GameState {
-    Turn(BlockType), GameWon(VictoryInfo),
 }
 
-pub fn initial_state() -> GameInfo {
+pub fn initial_state(settings: GameSettings) -> GameInfo {
     return GameInfo {
         cube_transform_matrix: mat4::create(),
         cube_rotation: quat::create(),
@@ -58,8 +64,20 @@ pub fn initial_state() -> GameInfo {
         cube_release_rotation: quat::create(),
         cube_release_time: 0.0,
 
-        blocks: [game::BlockType::None; (ROW_COUNT * ROW_COUNT * ROW_COUNT) as usize],
+        rules: rules::new_game(settings),
         last_block_id: -1,
+        save_path: record::DEFAULT_SAVE_PATH.to_string(),
+        turn_start_time: 0.0,
+        engine_job: None,
+        show_hints: false,
+        hints: Vec::new(),
+        hints_move_number: None,
+        show_solution: false,
+        solutions: solver::new_solution_table(),
+        solutions_changed: false,
+        solver_job: None,
+        solution: None,
+        solution_move_number: None,
         start_mouse_sphere_intersection: None,
         last_mouse_sphere_intersection: None,
         mouse_sphere_radius: 0.0,
@@ -70,126 +88,233 @@ pub fn initial_state() -> GameInfo {
         cube_size_mov: Movement::new(0.0, 1.0, 1.0, movement::EaseType::Ease, |val, state| {
             state.game.cube_size = val * BASE_CUBE_SIZE;
         }),
-
-        state: GameState::Turn(BlockType::Cross),
     };
 }
 
-pub fn pos_to_id(pos: &Vec3i) -> i32 {
-    return ((pos.x + ROW_COUNT) % ROW_COUNT)
-         + ((pos.y + ROW_COUNT) % ROW_COUNT) * ROW_COUNT 
-         + ((pos.z + ROW_COUNT) % ROW_COUNT) * ROW_COUNT * ROW_COUNT;
+// Empties the cube, the view is kept
+pub fn reset_game(settings: GameSettings, state: &mut State) {
+    cancel_engine_job(&mut state.game);
+
+    state.game.rules = rules::new_game(settings);
+    state.game.last_block_id = -1;
+    state.game.depth = 0;
+    state.game.turn_start_time = state.time.time;
+    state.game.hints_move_number = None;
+    forget_solution(&mut state.game);
 }
 
-pub fn get_block(pos: &Vec3i, state: &State) -> BlockType {
-    return state.game.blocks[pos_to_id(&pos) as usize];
+pub fn new_game(state: &mut State) {
+    let settings = state.game.rules.settings.clone();
+    reset_game(settings, state);
 }
 
-pub fn set_block(pos: &Vec3i, value: BlockType, state: &mut State) {
-    state.game.blocks[pos_to_id(&pos) as usize] = value;
+pub fn submit_click(chosen_pos: &Vec3i, state: &mut State) {
+    match rules::play_at(chosen_pos, state.time.time, &mut state.game.rules) {
+        Some(played) => {
+            println!("LOG: Played {}", notation::played_to_notation(&played));
+            on_move_played(state.time.time, &mut state.game);
+        },
+        None => {
+            // Already something here (or the column is full), maybe give some feedback to player
+        },
+    }
 }
 
-pub fn submit_click(pos: &Vec3i, state: &mut State) {
-    let current_block = get_block(pos, state);
+// Takes the first symbol with the pie rule, returns false if it isn't allowed now
+pub fn swap_sides(state: &mut State) -> bool {
+    match rules::swap_sides(state.time.time, &mut state.game.rules) {
+        Some(played) => {
+            println!("LOG: Swapped sides, {}", notation::played_to_notation(&played));
+            on_move_played(state.time.time, &mut state.game);
+            return true;
+        },
+        None => return false,
+    }
+}
 
-    let block_type = match state.game.state {
-        GameState::Turn(block_type) => block_type,
-        GameState::GameWon(_) => panic!(),
+// Replays a move that is known to be valid, like the ones of a save file
+pub fn play_move(played: Move, state: &mut State) {
+    rules::play_move(played, &mut state.game.rules);
+    on_move_played(state.time.time, &mut state.game);
+}
+
+fn on_move_played(time: f32, game: &mut GameInfo) {
+    let last = game.rules.history.last().expect("A move was just played");
+    game.last_block_id = pos_to_id(&last.position, &game.rules.board.config);
+    game.turn_start_time = time;
+    game.hints_move_number = None;
+    forget_solution(game);
+}
+
+// Takes back the last move, returns false if there is none
+pub fn undo(time: f32, game: &mut GameInfo) -> bool {
+    cancel_engine_job(game);
+
+    let undone = match rules::undo(&mut game.rules) {
+        Some(undone) => undone,
+        None => return false,
     };
+    println!("LOG: Took back {}", notation::played_to_notation(&undone));
 
-    if current_block == BlockType::None {
-        set_block(pos, block_type, state);
-        state.game.last_block_id = pos_to_id(pos);
-
-        let victory_info = check_for_victory(state);
-        match victory_info {
-            None => {
-                if block_type == BlockType::Cross {
-                    state.game.state = GameState::Turn(BlockType::Circle);
-                }
-                else {
-                    state.game.state = GameState::Turn(BlockType::Cross);
-                }
-            },
-            Some(info) => {
-                state.game.state = GameState::GameWon(info);
-            }
-        };
+    game.last_block_id = match game.rules.history.last() {
+        Some(previous) => pos_to_id(&previous.position, &game.rules.board.config),
+        None => -1,
+    };
+    game.turn_start_time = time;
+    game.hints_move_number = None;
+    forget_solution(game);
+
+    return true;
+}
+
+// Plays again the last undone move, returns false if there is none
+pub fn redo(time: f32, game: &mut GameInfo) -> bool {
+    cancel_engine_job(game);
+
+    match rules::redo(&mut game.rules) {
+        Some(redone) => {
+            println!("LOG: Played again {}", notation::played_to_notation(&redone));
+            on_move_played(time, game);
+            return true;
+        },
+        None => return false,
     }
-    else {
-        // Already something here, maybe give some feedback to player
+}
+
+// The hints are only found again when the position changed since the last time
+pub fn update_hints(player: u8, game: &mut GameInfo) {
+    let move_number = game.rules.history.len();
+    if game.hints_move_number != Some(move_number) {
+        game.hints = hints::find_hints(&game.rules.board, player, &game.rules.settings);
+        game.hints_move_number = Some(move_number);
     }
 }
 
-#[derive(Clone)]
-pub struct VictoryInfo {
-    pub winner: BlockType,
-    pub position: Vec3i,
-    pub direction: Vec3i,
-}
-
-pub fn check_for_victory(state: &State) -> Option<VictoryInfo> {
-    let deltas = [
-        [1, 0, 0],
-        [1, 1, 0],
-        [1, 1, 1],
-        [1, -1, 1],
-        [1, -1, -1],
-        [1, 1, -1],
-        [1, 0, -1],
-        [1, -1, 0],
-        [0, 1, 0],
-        [0, 0, 1],
-        [0, 1, 1],
-        [0, -1, 1],
-        [0, 1, -1],
-    ];
-
-    for x in 0..ROW_COUNT {
-        for y in 0..ROW_COUNT {
-            for z in 0..ROW_COUNT {
-                let block_type = get_block(&util::vec3i(x, y, z), state);
-
-                if block_type == BlockType::None {
-                    continue;
-                }
-
-                for j in 0..13 {
-                    let mut okay = true;
-                    for i in 0..COUNT_TO_WIN {
-                        if get_block(&util::vec3i(x + deltas[j][0] * i, y + deltas[j][1] * i, z + deltas[j][2] * i), state) != block_type {
-                            okay = false;
-                            break;
-                        }
-                    }
-
-                    if okay {
-                        return Some(VictoryInfo {
-                            winner: block_type,
-                            position: util::vec3i(x, y, z),
-                            direction: util::vec3i_arr(deltas[j]),
-                        });
-                    }
-                }
+// Human when the game is over
+pub fn get_current_controller(state: &State) -> Controller {
+    match state.game.rules.state {
+        GameState::Turn(player) => return state.game.rules.settings.players[player as usize].controller.clone(),
+        GameState::GameWon(_) | GameState::Draw => return Controller::Human,
+    }
+}
+
+// The engine thinks on another thread while the frames go on, its move is played after a short delay
+pub fn play_ai_turn(player: u8, controller: Controller, state: &mut State) {
+    let status = match &state.game.engine_job {
+        Some(job) => {
+            if state.time.time - state.game.turn_start_time < AI_MOVE_DELAY {
+                return;
             }
-        }
+            job::poll_job(job)
+        },
+        None => {
+            let move_number = state.game.rules.history.len();
+            let can_swap = rules::can_swap(&state.game.rules);
+            state.game.engine_job = Some(engine::start_job(&state.game.rules.board, player, &controller, &state.game.rules.settings, move_number, can_swap));
+            return;
+        },
+    };
+
+    match status {
+        job::JobStatus::Running => {},
+        job::JobStatus::Done(choice) => {
+            let job = state.game.engine_job.take().expect("");
+
+            // Should not happen as the job is cancelled when the position changes, but a stale move would be illegal
+            if job.move_number != state.game.rules.history.len() || job.player != player {
+                return;
+            }
+
+            match choice {
+                engine::Choice::Move(pos) => submit_click(&pos, state),
+                engine::Choice::Swap => { swap_sides(state); },
+                engine::Choice::NoMove => {
+                    // Asking again would start a failing program every frame
+                    println!("ERR: The engine of {} found no move, it is now played by hand", get_player_name(BlockType::Player(player), state));
+                    state.game.rules.settings.players[player as usize].controller = Controller::Human;
+                },
+            }
+        },
+        job::JobStatus::Failed => {
+            println!("ERR: The engine of {} stopped without choosing a move", get_player_name(BlockType::Player(player), state));
+            state.game.engine_job = None;
+        },
+    }
+}
+
+// When the position changes under the engine
+pub fn cancel_engine_job(game: &mut GameInfo) {
+    match game.engine_job.take() {
+        Some(job) => job::cancel_job(job),
+        None => {},
     }
+}
 
-    return None;
+// The position changed, the solver starts again from the new one when it is shown
+pub fn forget_solution(game: &mut GameInfo) {
+    match game.solver_job.take() {
+        Some(job) => job::cancel_job(job),
+        None => {},
+    }
+    game.solution = None;
+    game.solution_move_number = None;
 }
 
 pub fn get_current_player(state: &State) -> BlockType {
-    match &state.game.state {
-        GameState::Turn(player) => *player,
-        GameState::GameWon(info) => info.winner,
+    return rules::get_current_player(&state.game.rules);
+}
+
+pub fn get_player_name(player: BlockType, state: &State) -> String {
+    match player {
+        BlockType::Player(id) => state.game.rules.settings.players[id as usize].name.clone(),
+        BlockType::None => "Nobody".to_string(),
     }
 }
 
-pub fn get_player_color(player: BlockType, _state: &State) -> Vec4 {
-    if player == BlockType::Cross {
-        return CROSS_COLOR;
+pub fn get_player_color(player: BlockType, state: &State) -> Vec4 {
+    match player {
+        BlockType::Player(id) => state.game.rules.settings.players[id as usize].color,
+        BlockType::None => NO_PLAYER_COLOR,
     }
-    else {
-        return CIRCLE_COLOR;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::vec3i;
+
+    fn small_game() -> GameInfo {
+        let mut settings = settings::default_settings();
+        settings.board = board::parse_board_config("3").unwrap();
+        settings.board.wrap = board::parse_wrap("bounded").unwrap();
+        settings.count_to_win = 3;
+        return initial_state(settings);
+    }
+
+    fn play(pos: Vec3i, game: &mut GameInfo) {
+        rules::play_at(&pos, 0.0, &mut game.rules).expect("The block is free");
+        on_move_played(0.0, game);
+    }
+
+    fn has_block_hint(pos: Vec3i, game: &GameInfo) -> bool {
+        return game.hints.contains(&hints::Hint { position: pos, kind: hints::HintKind::Block });
+    }
+
+    #[test]
+    fn hints_follow_a_different_move_played_after_undo() {
+        let mut game = small_game();
+        play(vec3i(0, 0, 0), &mut game);
+        play(vec3i(2, 2, 2), &mut game);
+        play(vec3i(1, 0, 0), &mut game);
+        update_hints(1, &mut game);
+        assert!(has_block_hint(vec3i(2, 0, 0), &game));
+
+        // Same length of history, while the hints are hidden
+        assert!(undo(0.0, &mut game));
+        play(vec3i(0, 1, 0), &mut game);
+
+        update_hints(1, &mut game);
+        assert!(has_block_hint(vec3i(0, 2, 0), &game));
+        assert!(!has_block_hint(vec3i(2, 0, 0), &game));
     }
 }
@@ -1,5 +1,7 @@
 use crate::state;
 use crate::util;
+use std::collections::HashMap;
+use winit::event::VirtualKeyCode;
 
 pub struct Input {
     pub lmb: crate::input::ButtonInfo,
@@ -8,6 +10,8 @@ pub struct Input {
 
     pub wheel_up: bool,
     pub wheel_down: bool,
+
+    pub keys: HashMap<VirtualKeyCode, ButtonInfo>, // Only contains keys that have been used at least once
 }
 
 // Is a button pressed?
@@ -24,6 +28,7 @@ pub fn get_input() -> Input {
         mmb: get_info(),
         wheel_up: false,
         wheel_down: false,
+        keys: HashMap::new(),
     };
 }
 
@@ -75,6 +80,12 @@ pub fn get_events<T>(event: &winit::event::Event<T>, control_flow: &mut winit::e
                     winit::event::MouseButton::Other(_) => {},
                 }
             }
+            winit::event::WindowEvent::KeyboardInput { input, .. } => {
+                match input.virtual_keycode {
+                    Some(key) => update_info(&input.state, state.input.keys.entry(key).or_insert(get_info())),
+                    None => {},
+                }
+            }
             winit::event::WindowEvent::MouseWheel { device_id: _, delta, phase: _, .. } => {
                 match delta {
                     winit::event::MouseScrollDelta::LineDelta(_, y) => {
@@ -108,6 +119,18 @@ pub fn reset_input(state: &mut state::State) {
     reset_info(&mut state.input.rmb);
     reset_info(&mut state.input.mmb);
 
+    for info in state.input.keys.values_mut() {
+        reset_info(info);
+    }
+
     state.input.wheel_up = false;
     state.input.wheel_down = false;
 }
+
+// The key went from unpressed to pressed this frame (also true when the key repeats)
+pub fn is_key_down(key: VirtualKeyCode, state: &state::State) -> bool {
+    match state.input.keys.get(&key) {
+        Some(info) => return info.down,
+        None => return false,
+    }
+}
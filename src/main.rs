@@ -1,4 +1,5 @@
 #![allow(non_upper_case_globals, non_camel_case_types, non_snake_case, dead_code)]
+#![allow(clippy::needless_return, clippy::single_match)] // Same style as game-core
 
 mod bindings;
 
@@ -15,16 +16,20 @@ mod text;
 mod atlas_drawer;
 mod movement;
 mod ui;
+mod save;
 
 #[macro_use]
 extern crate glium;
 extern crate gl_matrix;
 extern crate csv;
 
+use game_core::{bitboard, board, engine, hints, job, notation, record, rules, settings, solver};
+
 use std::env;
 
 use game::GameState;
-use game::pos_to_id;
+use board::pos_to_id;
+use settings::Controller;
 use gl_matrix::quat;
 use gl_matrix::vec2;
 use gl_matrix::vec3;
@@ -63,7 +68,9 @@ fn main() {
     let cube_indices = glium::index::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &util::CUBE_INDICES).unwrap();
     
     // Initial state
-    let game_state = game::initial_state();
+    let options = settings::get_options_from_args();
+    let mut game_state = game::initial_state(options.settings.clone());
+    game_state.save_path = options.save_path.clone();
 
     let mut state = state::State {
         text_data: crate::text::empty_text_data(&display),
@@ -111,8 +118,10 @@ fn main() {
     // Compile shaders
     shader::create_shaders(&mut state);
 
-    texture::create("x.png", &mut state).put_in_assets(&mut state);
-    texture::create("o.png", &mut state).put_in_assets(&mut state);
+    // Symbols of all players, a loaded game may have more players than this one
+    for player in settings::get_player_presets(settings::MAX_PLAYER_COUNT) {
+        texture::create(&player.symbol, &mut state).put_in_assets(&mut state);
+    }
     texture::create("gray_grid.png", &mut state).set_filtering(texture::FilterType::Nearest).put_in_assets(&mut state);
     texture::create("sandwich.png", &mut state).put_in_assets(&mut state);
 
@@ -126,6 +135,24 @@ fn main() {
 
     start(&mut state);
 
+    match solver::load_solutions(solver::DEFAULT_SOLUTIONS_PATH) {
+        Ok(table) => state.game.solutions = table,
+        Err(msg) => println!("ERR: {}", msg),
+    }
+
+    match options.load_path {
+        Some(path) => {
+            match save::load_game(&path, &mut state) {
+                Ok(()) => println!("LOG: Loaded game from {}", path),
+                Err(msg) => {
+                    println!("ERR: {}", msg);
+                    std::process::exit(1);
+                }
+            }
+        },
+        None => {},
+    }
+
     event_loop.run(move |event, _, control_flow| {
 
         // Remember last mouse position
@@ -133,6 +160,26 @@ fn main() {
 
         input::get_events(&event, control_flow, &mut state);
 
+        // Don't leave an engine thinking after the window is closed
+        if *control_flow == winit::event_loop::ControlFlow::Exit {
+            match state.game.engine_job.take() {
+                Some(job) => job::stop_job(job),
+                None => {},
+            }
+            match state.game.solver_job.take() {
+                Some(job) => job::stop_job(job),
+                None => {},
+            }
+
+            if state.game.solutions_changed {
+                match solver::save_solutions(solver::DEFAULT_SOLUTIONS_PATH, &state.game.solutions) {
+                    Ok(()) => println!("LOG: Saved {} solved positions to {}", state.game.solutions.positions.len(), solver::DEFAULT_SOLUTIONS_PATH),
+                    Err(msg) => println!("ERR: {}", msg),
+                }
+                state.game.solutions_changed = false;
+            }
+        }
+
         // Get mouse delta position
         vec2::sub(&mut state.mouse_delta_normalized, &state.mouse_coords_normalized, &old_mous_pos);
 
@@ -193,43 +240,54 @@ fn main_loop(state: &mut State) {
     // Get intersection with cube
     let pos_on_cube = get_mouse_pos_on_cube(&state);
 
-    // Draw lines
-    for i in [0, 6, 1, 2, 3, 4, 5] {
-        for j in [0, 6, 1, 2, 3, 4, 5] {
-            let half_size = state.game.cube_size / 2.0;
-
-            let i_norm = state.game.cube_size / (game::ROW_COUNT as f32) * i as f32 - half_size;
-            let j_norm = state.game.cube_size / (game::ROW_COUNT as f32) * j as f32 - half_size;
-
-            let a = apply_cube_transform(&[i_norm, -half_size, j_norm], &state);
-            let b = apply_cube_transform(&[i_norm,  half_size, j_norm], &state);
-            let c = apply_cube_transform(&[-half_size, i_norm, j_norm], &state);
-            let d = apply_cube_transform(&[ half_size, i_norm, j_norm], &state);
-            let e = apply_cube_transform(&[i_norm, j_norm, -half_size], &state);
-            let f = apply_cube_transform(&[i_norm, j_norm,  half_size], &state);
-
-            let corner = (i == 0 || i == 6) && 
-                         (j == 0 || j == 6);
-            let border = (i == 0 || i == 6) ||
-                         (j == 0 || j == 6);
-
-            if border {
-                let width = if corner { 0.01 } else { 0.002 };
-                let color = if corner { [0.8, 0.8, 0.8, 1.0] } else { [0.8, 0.8, 0.8, 0.2] };
-    
-                draw::draw_line_world(&a, &b, color, width, !corner, state);
-                draw::draw_line_world(&c, &d, color, width, !corner, state);
-                draw::draw_line_world(&e, &f, color, width, !corner, state);
-            } 
+    // Draw lines along each axis, on the faces of the cube
+    let size = state.game.rules.board.config.size;
+    let block_size = get_block_size(state);
+    let half_extents = get_half_extents(state);
+    let floor = get_floor_line(state);
+    for axis in 0..3 {
+        let tangent1 = (axis + 1) % 3;
+        let tangent2 = (axis + 2) % 3;
+
+        for i in 0..(size[tangent1] + 1) {
+            for j in 0..(size[tangent2] + 1) {
+                let border_i = i == 0 || i == size[tangent1];
+                let border_j = j == 0 || j == size[tangent2];
+
+                let corner = border_i && border_j;
+                let border = border_i || border_j;
+
+                if border {
+                    let mut a = [0.0; 3];
+                    a[tangent1] = block_size * i as f32 - half_extents[tangent1];
+                    a[tangent2] = block_size * j as f32 - half_extents[tangent2];
+                    let mut b = a;
+                    a[axis] = -half_extents[axis];
+                    b[axis] = half_extents[axis];
+
+                    let width = if corner { 0.01 } else { 0.002 };
+                    let mut color = if corner { [0.8, 0.8, 0.8, 1.0] } else { [0.8, 0.8, 0.8, 0.2] };
+
+                    // Lines of the face symbols fall on
+                    let on_floor = floor == Some((tangent1, i)) || floor == Some((tangent2, j));
+                    if on_floor {
+                        color = [game::FLOOR_COLOR[0], game::FLOOR_COLOR[1], game::FLOOR_COLOR[2], color[3]];
+                    }
+
+                    let a = apply_cube_transform(&a, &state);
+                    let b = apply_cube_transform(&b, &state);
+                    draw::draw_line_world(&a, &b, color, width, !corner, state);
+                }
+            }
         }
     }
 
     // Draw crosses and circles
-    for i in 0..game::ROW_COUNT {
-        for j in 0..game::ROW_COUNT {
-            for k in 0..game::ROW_COUNT {
+    for i in 0..size[0] {
+        for j in 0..size[1] {
+            for k in 0..size[2] {
                 let pos = vec3i(i, j, k);
-                let block_type = game::get_block(&pos, &state);
+                let block_type = board::get_block(&pos, &state.game.rules.board);
                 let position = apply_cube_transform(&get_block_coords(&pos, &state), &state);
 
                 if block_type != game::BlockType::None {
@@ -239,12 +297,12 @@ fn main_loop(state: &mut State) {
                     let mut symbol_color = [1.0, 1.0, 1.0, 1.0];
                     
                     // Highlight last placed block
-                    if pos_to_id(&pos) == state.game.last_block_id {
+                    if pos_to_id(&pos, &state.game.rules.board.config) == state.game.last_block_id {
                         symbol_color = util::lerp_vec4(&symbol_color, &game::HIGHLIGHT_COLOR, (game::HIGHLIGHT_SPEED * state.time.time).sin() * 0.5 + 0.5);
                     }
 
                     draw::draw_world_billboard(position, [0.05, 0.05], 0.0, symbol_color, 
-                        draw::TexArg::One(get_symbol_texture(block_type).to_string()), &"default_tex", state);
+                        draw::TexArg::One(get_symbol_texture(block_type, state)), &"default_tex", state);
 
                     draw_cube_on_block(&pos, color, &"default_color", state);
                 }
@@ -322,12 +380,76 @@ fn main_loop(state: &mut State) {
         }
     }
 
-    match state.game.state {
+    // Undo / redo
+    // The moves of the computer are skipped, to get back to a human turn
+    if input::is_key_down(winit::event::VirtualKeyCode::Z, state) {
+        while game::undo(state.time.time, &mut state.game) && game::get_current_controller(state) != Controller::Human {}
+    }
+    else if input::is_key_down(winit::event::VirtualKeyCode::Y, state) {
+        while game::redo(state.time.time, &mut state.game) && game::get_current_controller(state) != Controller::Human {}
+    }
+
+    // Hints for the current player
+    if input::is_key_down(winit::event::VirtualKeyCode::H, state) {
+        state.game.show_hints = !state.game.show_hints;
+    }
+
+    // Value of the position with perfect play, on small cubes
+    if input::is_key_down(winit::event::VirtualKeyCode::V, state) {
+        state.game.show_solution = !state.game.show_solution;
+    }
+
+    // Pie rule: the second player takes the first symbol
+    if input::is_key_down(winit::event::VirtualKeyCode::S, state) && game::get_current_controller(state) == Controller::Human {
+        game::swap_sides(state);
+    }
+
+    // New game with the same settings
+    if input::is_key_down(winit::event::VirtualKeyCode::N, state) {
+        game::new_game(state);
+        println!("LOG: New game");
+    }
+
+    // Save / load
+    if input::is_key_down(winit::event::VirtualKeyCode::F5, state) {
+        let path = state.game.save_path.clone();
+        match save::save_game(&path, state) {
+            Ok(()) => println!("LOG: Saved game to {}", path),
+            Err(msg) => println!("ERR: {}", msg),
+        }
+    }
+    else if input::is_key_down(winit::event::VirtualKeyCode::F9, state) {
+        let path = state.game.save_path.clone();
+        match save::load_game(&path, state) {
+            Ok(()) => println!("LOG: Loaded game from {}", path),
+            Err(msg) => println!("ERR: {}", msg),
+        }
+    }
+
+    match state.game.rules.state {
         GameState::GameWon(_) => {
             draw_line_of_winner(state);
         },
-        GameState::Turn(_) => {
-            handle_turn(pos_on_cube, state);
+        GameState::Draw => {
+            draw_draw_overlay(state);
+        },
+        GameState::Turn(player) => {
+            if state.game.show_hints {
+                draw_hints(player, state);
+            }
+            if state.game.show_solution {
+                draw_solution(player, state);
+            }
+
+            match game::get_current_controller(state) {
+                Controller::Human => {
+                    if rules::can_swap(&state.game.rules) {
+                        draw_swap_offer(player, state);
+                    }
+                    handle_turn(pos_on_cube, state);
+                },
+                controller => game::play_ai_turn(player, controller, state),
+            }
         }
     };
 
@@ -350,6 +472,9 @@ fn main_loop(state: &mut State) {
         &format!("{}ms, {} FPS", (state.time.delta_time * 1000.0) as i32, (1.0 / state.time.delta_time) as i32), 
         [0.005, 0.98], 0.025, [1.0, 1.0, 1.0, 1.0], state);
 
+    draw_player_list(state);
+    draw_move_history(state);
+
     // Make sure the cube rotation is normalized (sometimes isn't because of precision issues) 
     let mut normalized = quat::create();
     quat::normalize(&mut normalized, &state.game.cube_rotation);
@@ -366,35 +491,51 @@ fn handle_turn(pos_on_cube: Option<CubePosition>, state: &mut State) {
         let pos = pos_on_cube.expect("");
 
         // Handle wheel
+        let row_count = state.game.rules.board.config.size[pos.wheel_direction];
         if state.input.wheel_down {
             if state.game.depth > 0 {
                 state.game.depth -= 1;
             }
         }
         else if state.input.wheel_up {
-            if state.game.depth < game::ROW_COUNT - 1 {
+            if state.game.depth < row_count - 1 {
                 state.game.depth += 1;
             }
         }
 
+        // The cube may be shorter along this axis than along the previous one
+        state.game.depth = state.game.depth.min(row_count - 1);
+
         let mut block_pos = pos.coords;
         if pos.is_wheel_inverted {
-            block_pos[pos.wheel_direction] = game::ROW_COUNT - 1 - state.game.depth;
+            block_pos[pos.wheel_direction] = row_count - 1 - state.game.depth;
         }
         else {
             block_pos[pos.wheel_direction] = state.game.depth;
         }
 
         let pos_vec = util::vec3i_arr(block_pos);
+        let landing_pos = game::get_landing_pos(&pos_vec, &state.game.rules.board, &state.game.rules.settings);
+
+        // Show where the symbol will actually end up
+        if let Some(landing_pos) = landing_pos {
+            let billboard_pos = apply_cube_transform(&get_block_coords(&landing_pos, &state), &state);
+            let color = vec4::from_values(1.0, 1.0, 1.0, (state.time.time * 10.0).sin() * 0.25 + 0.75);
+            draw::draw_world_billboard(billboard_pos, [0.05, 0.05], 0.0, color,
+                draw::TexArg::One(get_symbol_texture_of_turn(state)), "default_tex", state);
+        }
 
-        let billboard_pos = apply_cube_transform(&get_block_coords(&pos_vec, &state), &state);
-        let color = vec4::from_values(1.0, 1.0, 1.0, (state.time.time * 10.0).sin() * 0.25 + 0.75);
-        draw::draw_world_billboard(billboard_pos, [0.05, 0.05], 0.0, color,
-            draw::TexArg::One(get_symbol_texture_of_turn(state).to_string()), "default_tex", state);
-
-        draw_column_outline(&block_pos, 0, state);
-        draw_column_outline(&block_pos, 1, state);
-        draw_column_outline(&block_pos, 2, state);
+        match state.game.rules.settings.placement.clone() {
+            settings::PlacementMode::Free => {
+                draw_column_outline(&block_pos, 0, state);
+                draw_column_outline(&block_pos, 1, state);
+                draw_column_outline(&block_pos, 2, state);
+            },
+            settings::PlacementMode::Gravity(direction) => {
+                // Only the column in which the symbol falls
+                draw_column_outline(&block_pos, settings::get_gravity_axis(&direction), state);
+            },
+        }
 
         // Submit
         if state.input.rmb.up {
@@ -422,13 +563,50 @@ fn apply_cube_rotation(vec: &Vec3, state: &State) -> Vec3 {
     return res;
 }
 
+// Axis and line index of the face symbols fall on, if there is gravity
+fn get_floor_line(state: &State) -> Option<(usize, i32)> {
+    match &state.game.rules.settings.placement {
+        settings::PlacementMode::Free => return None,
+        settings::PlacementMode::Gravity(direction) => {
+            let axis = settings::get_gravity_axis(direction);
+            let mut falls_to_end = util::vec3i_to_arr(direction)[axis] > 0;
+
+            // z is inverted when converting to world coordinates
+            if axis == 2 {
+                falls_to_end = !falls_to_end;
+            }
+
+            let line = if falls_to_end { state.game.rules.board.config.size[axis] } else { 0 };
+            return Some((axis, line));
+        }
+    }
+}
+
+// Blocks are cubes, so the longest side of the board spans the whole cube_size
+fn get_block_size(state: &State) -> f32 {
+    return state.game.cube_size / board::get_max_row_count(&state.game.rules.board.config) as f32;
+}
+
+// Half of the size of the board along each axis
+fn get_half_extents(state: &State) -> Vec3 {
+    let block_size = get_block_size(state);
+    let size = &state.game.rules.board.config.size;
+
+    return [
+        block_size * size[0] as f32 / 2.0,
+        block_size * size[1] as f32 / 2.0,
+        block_size * size[2] as f32 / 2.0,
+    ];
+}
+
 fn get_block_coords(pos: &Vec3i, state: &State) -> Vec3 {
-    let block_size = state.game.cube_size / game::ROW_COUNT as f32;
+    let block_size = get_block_size(state);
+    let size = &state.game.rules.board.config.size;
     
     return [
-        -(block_size * (game::ROW_COUNT - 1) as f32 / 2.0) + pos.x as f32 * block_size,
-        -(block_size * (game::ROW_COUNT - 1) as f32 / 2.0) + pos.y as f32 * block_size,
-        (block_size * (game::ROW_COUNT - 1) as f32 / 2.0) - pos.z as f32 * block_size,
+        -(block_size * (size[0] - 1) as f32 / 2.0) + pos.x as f32 * block_size,
+        -(block_size * (size[1] - 1) as f32 / 2.0) + pos.y as f32 * block_size,
+        (block_size * (size[2] - 1) as f32 / 2.0) - pos.z as f32 * block_size,
     ];
 }
 
@@ -488,9 +666,19 @@ fn get_mouse_pos_on_cube(state: &State) -> Option<CubePosition> {
         [1, 2, -3],
     ];
 
+    let half_extents = get_half_extents(state);
+    let block_size = get_block_size(state);
+    let size = &state.game.rules.board.config.size;
+
     for i in 0..6 {
+        let scaled_origin = [
+            origin[i][0] * half_extents[0],
+            origin[i][1] * half_extents[1],
+            origin[i][2] * half_extents[2],
+        ];
+
         let transformed_normal = apply_cube_rotation(&normals[i], &state);
-        let transformed_origin = apply_cube_transform(&origin[i], &state);
+        let transformed_origin = apply_cube_transform(&scaled_origin, &state);
 
         // Ignore if facing away
         if !is_pointing_towards_camera(&transformed_origin, &transformed_normal) {
@@ -503,25 +691,28 @@ fn get_mouse_pos_on_cube(state: &State) -> Option<CubePosition> {
         let intersection = util::intersect_line_plane(&transformed_origin, &transformed_normal, &[0.0, 0.0, 0.0], &state.mouse_ray);
         let plane_coords = util::get_plane_coords(&transformed_origin, &transformed_t1, &transformed_t2, &intersection);
 
-        if plane_coords[0] < 0.0 || plane_coords[0] > state.game.cube_size || plane_coords[1] < 0.0 || plane_coords[1] > state.game.cube_size {
+        let t1_axis = (coords[i][0] - 1) as usize;
+        let t2_axis = (coords[i][1] - 1) as usize;
+
+        if plane_coords[0] < 0.0 || plane_coords[0] > 2.0 * half_extents[t1_axis] || plane_coords[1] < 0.0 || plane_coords[1] > 2.0 * half_extents[t2_axis] {
             continue;
         }
 
         let block_coords = [
-            (plane_coords[0] / state.game.cube_size * 6.0).floor() as i32,
-            (plane_coords[1] / state.game.cube_size * 6.0).floor() as i32,
+            ((plane_coords[0] / block_size).floor() as i32).min(size[t1_axis] - 1),
+            ((plane_coords[1] / block_size).floor() as i32).min(size[t2_axis] - 1),
         ];
 
         let mut res = [0, 0, 0];
-        res[(coords[i][0] - 1) as usize] = block_coords[0];
-        res[(coords[i][1] - 1) as usize] = block_coords[1];
+        res[t1_axis] = block_coords[0];
+        res[t2_axis] = block_coords[1];
 
         return Some(CubePosition {
             world_pos: intersection,
             coords: res,
             wheel_direction: (coords[i][2].abs() - 1) as usize,
-            tangent1: (coords[i][0] - 1) as usize,
-            tangent2: (coords[i][1] - 1) as usize,
+            tangent1: t1_axis,
+            tangent2: t2_axis,
             is_wheel_inverted: coords[i][2] > 0,
             face_id: i as i32,
         });
@@ -530,22 +721,15 @@ fn get_mouse_pos_on_cube(state: &State) -> Option<CubePosition> {
     return None;
 }
 
-fn get_symbol_texture(t: game::BlockType) -> &'static str {
-    if t == game::BlockType::Cross {
-        return "x.png";
-    }
-    else {
-        return "o.png";
+fn get_symbol_texture(t: game::BlockType, state: &State) -> String {
+    match t {
+        game::BlockType::Player(id) => return state.game.rules.settings.players[id as usize].symbol.clone(),
+        game::BlockType::None => panic!("Empty blocks have no symbol"),
     }
 }
 
-fn get_symbol_texture_of_turn(state: &State) -> &'static str {
-    let block_type = match state.game.state {
-        GameState::Turn(block_type) => block_type,
-        GameState::GameWon(ref info) => info.winner,
-    };
-
-    return get_symbol_texture(block_type);
+fn get_symbol_texture_of_turn(state: &State) -> String {
+    return get_symbol_texture(game::get_current_player(state), state);
 }
 
 fn draw_cube_on_block<'a>(pos: &Vec3i, color: Vec4, shader: &'a str, state: &mut State<'a>) {
@@ -556,7 +740,7 @@ fn draw_cube_on_block<'a>(pos: &Vec3i, color: Vec4, shader: &'a str, state: &mut
     let mut result_transform = mat4::create();
     mat4::mul(&mut result_transform, &state.game.cube_transform_matrix, &translate_mat);
     
-    let scale_amount = state.game.cube_size / game::ROW_COUNT as f32;
+    let scale_amount = get_block_size(state);
 
     let cloned = result_transform.clone();
     mat4::scale(&mut result_transform, &cloned, &[scale_amount, scale_amount, scale_amount]);
@@ -564,60 +748,179 @@ fn draw_cube_on_block<'a>(pos: &Vec3i, color: Vec4, shader: &'a str, state: &mut
     draw::draw_cube(result_transform, scale_amount, color, draw::TexArg::None, shader, state)
 }
 
-pub fn draw_line_of_winner(state: &mut State) {
-    match state.game.state.clone() {
-        game::GameState::GameWon(info) => {
-            let mut color = game::get_player_color(info.winner, &state);
-            color[3] = 0.7;
-            
-            let mut first_point = info.position.clone();
-
-            first_point.x = (first_point.x + game::ROW_COUNT) % game::ROW_COUNT;
-            first_point.y = (first_point.y + game::ROW_COUNT) % game::ROW_COUNT;
-            first_point.z = (first_point.z + game::ROW_COUNT) % game::ROW_COUNT;
-
-            let mut current = first_point.clone();
-            let mut total_length = game::ROW_COUNT - 1;
-            let mut i = 0;
-            let mut should_end = false;
-            while !should_end {
-                if i < total_length - 1 {
-                    current.x += info.direction.x;
-                    current.y += info.direction.y;
-                    current.z += info.direction.z;
-                }
-                else {
-                    should_end = true;
-                }
+// The analysis only runs again when a move is played or taken back
+fn draw_hints(player: u8, state: &mut State) {
+    game::update_hints(player, &mut state.game);
 
-                if current.x < 0 || current.x >= game::ROW_COUNT 
-                || current.y < 0 || current.y >= game::ROW_COUNT 
-                || current.z < 0 || current.z >= game::ROW_COUNT 
-                || i == total_length - 1 { // Fell outside of the cube
+    let alpha = (game::HIGHLIGHT_SPEED * state.time.time).sin() * 0.15 + 0.35;
+    for hint in state.game.hints.clone() {
+        let mut color = match hint.kind {
+            hints::HintKind::Win => game::HINT_WIN_COLOR,
+            hints::HintKind::Block => game::HINT_BLOCK_COLOR,
+            hints::HintKind::OpenFour => game::HINT_OPEN_FOUR_COLOR,
+        };
+        color[3] = alpha;
 
-                    draw::draw_line_world(
-                        &apply_cube_transform(&get_block_coords(&first_point, state), state), 
-                        &apply_cube_transform(&get_block_coords(&current, state), state), 
-                        color, 0.03, false, state);
+        draw_cube_on_block(&hint.position, color, &"default_color", state);
+    }
+}
 
-                    current.x = (current.x + game::ROW_COUNT) % game::ROW_COUNT;
-                    current.y = (current.y + game::ROW_COUNT) % game::ROW_COUNT;
-                    current.z = (current.z + game::ROW_COUNT) % game::ROW_COUNT;
+// Known positions are shown right away, the other ones are solved on another thread
+fn draw_solution(player: u8, state: &mut State) {
+    let move_number = state.game.rules.history.len();
+    if state.game.solution_move_number != Some(move_number) {
+        game::forget_solution(&mut state.game);
+        state.game.solution = solver::lookup(&state.game.rules.board, player, &state.game.rules.settings, &state.game.solutions);
+        if state.game.solution.is_none() && solver::can_solve(&state.game.rules) {
+            state.game.solver_job = Some(solver::start_job(&state.game.rules.board, player, &state.game.rules.settings, move_number));
+        }
+        state.game.solution_move_number = Some(move_number);
+    }
+
+    let status = match &state.game.solver_job {
+        Some(job) => job::poll_job(job),
+        None => job::JobStatus::Failed,
+    };
+    match status {
+        job::JobStatus::Running => {},
+        job::JobStatus::Done(value) => {
+            state.game.solver_job = None;
+            match value {
+                Some(value) => {
+                    solver::insert(&state.game.rules.board, player, value, &state.game.rules.settings, &mut state.game.solutions);
+                    state.game.solutions_changed = true;
+                    state.game.solution = Some(value);
+                },
+                None => println!("LOG: The position has too many continuations to be solved"),
+            }
+        },
+        job::JobStatus::Failed => state.game.solver_job = None,
+    }
 
-                    current.x -= info.direction.x;
-                    current.y -= info.direction.y;
-                    current.z -= info.direction.z;
+    let content = match (&state.game.solution, &state.game.solver_job) {
+        (Some(value), _) => solver::describe_value(value, player, &state.game.rules.settings),
+        (None, Some(_)) => "Solving...".to_string(),
+        (None, None) => "Too large to be solved".to_string(),
+    };
+    text::draw_text(&content, [0.005, 0.05], 0.03, [1.0, 1.0, 1.0, 1.0], state);
+}
 
-                    first_point = current.clone();
-                    total_length += 1
-                }
-                i += 1;
+pub fn draw_line_of_winner(state: &mut State) {
+    match state.game.rules.state.clone() {
+        game::GameState::GameWon(lines) => {
+            for info in &lines {
+                draw_winning_line(info, state);
             }
+
+            let winner = lines[0].winner;
+            let message = if lines.len() > 1 {
+                format!("{} wins with {} lines!", game::get_player_name(winner, state), lines.len())
+            }
+            else {
+                format!("{} wins!", game::get_player_name(winner, state))
+            };
+
+            draw_centered_text(&message, 0.85, 0.05, game::get_player_color(winner, &state), state);
         },
         _ => panic!("Uuh?"),
     }
 }
 
+fn draw_winning_line(info: &game::VictoryInfo, state: &mut State) {
+    let mut color = game::get_player_color(info.winner, &state);
+    color[3] = 0.7;
+    
+    let config = state.game.rules.board.config.clone();
+    let mut first_point = board::wrap_pos(&info.position, &config);
+    let mut current = first_point.clone();
+
+    // The line is split in several segments if it wraps around the cube
+    for _ in 1..state.game.rules.settings.count_to_win {
+        current.x += info.direction.x;
+        current.y += info.direction.y;
+        current.z += info.direction.z;
+
+        if !board::is_in_cube(&current, &config) { // Fell outside of the cube, draw up to the edge
+            draw::draw_line_world(
+                &apply_cube_transform(&get_block_coords(&first_point, state), state), 
+                &apply_cube_transform(&get_block_coords(&current, state), state), 
+                color, 0.03, false, state);
+
+            current = board::wrap_pos(&current, &config);
+
+            // Start again from outside of the opposite face
+            first_point = current.clone();
+            first_point.x -= info.direction.x;
+            first_point.y -= info.direction.y;
+            first_point.z -= info.direction.z;
+        }
+    }
+
+    draw::draw_line_world(
+        &apply_cube_transform(&get_block_coords(&first_point, state), state), 
+        &apply_cube_transform(&get_block_coords(&current, state), state), 
+        color, 0.03, false, state);
+}
+
+// Players in turn order, the one that is playing is marked
+fn draw_player_list(state: &mut State) {
+    let current = game::get_current_player(state);
+    let turn_order = state.game.rules.settings.turn_order.clone();
+
+    for (i, id) in turn_order.into_iter().enumerate() {
+        let player = game::BlockType::Player(id);
+        let marker = if player == current { "> " } else { "  " };
+        let controller = match state.game.rules.settings.players[id as usize].controller {
+            Controller::Human => "",
+            Controller::AlphaBeta(_) | Controller::Mcts(_) => " (computer)",
+            Controller::External(_) => " (engine)",
+        };
+        let content = format!("{}{}{}", marker, game::get_player_name(player, state), controller);
+        let color = game::get_player_color(player, state);
+
+        text::draw_text(&content, [0.005, 0.93 - 0.04 * i as f32], 0.03, color, state);
+    }
+}
+
+// The last moves in the top right corner, the most recent at the bottom
+fn draw_move_history(state: &mut State) {
+    const SHOWN_MOVE_COUNT: usize = 20;
+    let screen_width = state.resolution.x as f32 / state.resolution.y as f32;
+
+    let history = state.game.rules.history.clone();
+    let first = history.len().saturating_sub(SHOWN_MOVE_COUNT);
+    for (i, played) in history.iter().enumerate().skip(first) {
+        let content = format!("{}. {}", i + 1, notation::played_to_notation(played));
+        let color = game::get_player_color(game::BlockType::Player(played.player), state);
+
+        text::draw_text(&content, [screen_width - 0.25, 0.93 - 0.04 * (i - first) as f32], 0.03, color, state);
+    }
+}
+
+fn draw_swap_offer(player: u8, state: &mut State) {
+    let message = format!("{}: press S to take the first move as yours", game::get_player_name(game::BlockType::Player(player), state));
+    draw_centered_text(&message, 0.85, 0.04, game::get_player_color(game::BlockType::Player(player), &state), state);
+}
+
+pub fn draw_draw_overlay(state: &mut State) {
+    let message = if bitboard::is_full(&state.game.rules.bits, &state.game.rules.board.config) {
+        "Draw! The cube is full".to_string()
+    }
+    else {
+        format!("Draw! Nobody can align {} symbols anymore", state.game.rules.settings.count_to_win)
+    };
+
+    draw_centered_text(&message, 0.85, 0.05, [1.0, 1.0, 1.0, 1.0], state);
+}
+
+// y is the position of the bottom of the text, 1 is the top of the screen
+fn draw_centered_text(content: &str, y: f32, size: f32, color: Vec4, state: &mut State) {
+    let screen_width = state.resolution.x as f32 / state.resolution.y as f32;
+    let text_width = text::get_text_width(content, size, state);
+
+    text::draw_text(content, [(screen_width - text_width) / 2.0, y], size, color, state);
+}
+
 pub fn draw_column_outline(_pos: &[i32; 3], axis: usize, state: &mut State) {
     let mut pos = _pos.clone();
 
@@ -632,10 +935,10 @@ pub fn draw_column_outline(_pos: &[i32; 3], axis: usize, state: &mut State) {
     
     pos[axis] = 0;
     let mut a = get_block_coords(&util::vec3i_arr(pos), &state);
-    pos[axis] = game::ROW_COUNT - 1;
+    pos[axis] = state.game.rules.board.config.size[axis] - 1;
     let mut b = get_block_coords(&util::vec3i_arr(pos), &state);
 
-    let mut half_block_size = state.game.cube_size / game::ROW_COUNT as f32 / 2.0;
+    let mut half_block_size = get_block_size(state) / 2.0;
     
     if axis == 2 {
         half_block_size *= -1.0;
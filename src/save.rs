@@ -0,0 +1,40 @@
+use crate::game;
+use crate::record;
+use crate::state::State;
+use gl_matrix::quat;
+
+// The file format lives in game-core, this adds the parts that only exist in the window
+pub fn save_game(path: &str, state: &State) -> Result<(), String> {
+    let record = record::create_record(&state.game.rules, state.game.cube_rotation);
+    return record::write_record(path, &record);
+}
+
+// Replaces the current game with the one in the file
+pub fn load_game(path: &str, state: &mut State) -> Result<(), String> {
+    let record = record::read_record(path)?;
+
+    // Who plays each symbol is not saved, keep the current choice and opening book
+    let mut settings = record::record_to_settings(&record.settings)?;
+    for (player, current) in settings.players.iter_mut().zip(&state.game.rules.settings.players) {
+        player.controller = current.controller.clone();
+    }
+    settings.book = state.game.rules.settings.book.clone();
+
+    // Check the moves before touching the current game
+    let moves = record::get_record_moves(&record, &settings)?;
+
+    // Start a new game with these settings and play the moves again
+    game::reset_game(settings, state);
+
+    for played in moves {
+        game::play_move(played, state);
+    }
+
+    // Stop the cube where it was
+    state.game.cube_rotation = record.cube_rotation;
+    state.game.cube_release_rotation = record.cube_rotation;
+    quat::identity(&mut state.game.cube_rotation_velocity);
+    state.game.cube_release_time = state.time.time;
+
+    return Ok(());
+}
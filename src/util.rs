@@ -6,19 +6,15 @@ use crate::gl_matrix::common::*;
 use crate::Vertex;
 use crate::state::State;
 
+// The integer vectors are shared with the rules
+pub use game_core::util::*;
+
 #[derive(Clone)]
 pub struct Vec2i {
     pub x: i32,
     pub y: i32,
 } 
 
-#[derive(Clone, PartialEq)]
-pub struct Vec3i {
-    pub x: i32,
-    pub y: i32,
-    pub z: i32,
-} 
-
 #[derive(Clone)]
 pub struct Vec2u {
     pub x: u32,
@@ -33,18 +29,6 @@ pub fn vec2u(x: u32, y: u32) -> Vec2u {
     return Vec2u { x, y };
 }
 
-pub fn vec3i(x: i32, y: i32, z: i32) -> Vec3i {
-    return Vec3i { x, y, z };
-}
-
-pub fn vec3i_arr(coords: [i32; 3]) -> Vec3i {
-    return Vec3i { x: coords[0], y: coords[1], z: coords[2] };
-}
-
-pub fn vec3i_to_arr(vec: &Vec3i) -> [i32; 3] {
-    return [vec.x, vec.y, vec.z];
-}
-
 pub fn mat_to_uniform(mat: &Mat4) -> [[f32; 4]; 4] {
     return [
         [mat[0], mat[1], mat[2], mat[3]],
@@ -0,0 +1,486 @@
+// Plays the game in a terminal: the cube is shown as one 2D slice per z coordinate,
+// and the moves are typed in the same notation as the history of the window
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use game_core::board::*;
+use game_core::rules::{BlockType, GameState};
+use game_core::settings::Controller;
+use game_core::util::*;
+use game_core::{bitboard, engine, hints, notation, record, rules, settings, solver};
+use std::io::{BufRead, Write};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+const SLICES_PER_ROW: usize = 3;
+const SLICE_GAP: &str = "    ";
+const IDENTITY_ROTATION: [f32; 4] = [0.0, 0.0, 0.0, 1.0]; // Saved as the rotation of the cube, there is none here
+
+const HELP: &str = "\
+Commands:
+    c2.4 or X c2.4    Play on this block: x is a letter, y and z are numbers starting at 1
+    undo, redo        Take back or play again the last move, the moves of the computer are skipped
+    hints             List the blocks that win, stop a win or leave two ways to win
+    swap              With the pie rule, take the first symbol of the opponent as your own
+    solve             Tell who wins with perfect play, on cubes of up to 64 blocks with 2 players
+    new               Start a new game with the same settings
+    save [path]       Save the game, to the --save path by default
+    load [path]       Continue a saved game, from the --save path by default
+    help              Show this list
+    quit              Leave the game";
+
+enum Command {
+    Play(Vec3i),
+    Undo,
+    Redo,
+    Hints,
+    Swap,
+    Solve,
+    New,
+    Save(Option<String>),
+    Load(Option<String>),
+    Help,
+    Quit,
+}
+
+struct Session {
+    game: rules::Game,
+    save_path: String,
+    start_time: Instant, // The moves are timed from here, like in the window
+}
+
+fn main() {
+    let options = settings::get_options_from_args();
+
+    let mut session = Session {
+        game: rules::new_game(options.settings),
+        save_path: options.save_path,
+        start_time: Instant::now(),
+    };
+
+    match &options.load_path {
+        Some(path) => {
+            match load_game(path, &mut session) {
+                Ok(()) => println!("Loaded {}", path),
+                Err(msg) => {
+                    println!("ERR: {}", msg);
+                    std::process::exit(1);
+                },
+            }
+        },
+        None => {},
+    }
+
+    println!("{}", get_settings_summary(&session.game.settings));
+    println!("Type help for the list of commands");
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut changed = true;
+    loop {
+        if changed {
+            println!();
+            println!("{}", draw_board(&session.game));
+            println!("{}", get_status(&session.game));
+            changed = false;
+        }
+
+        // The computer doesn't wait for input
+        match session.game.state {
+            GameState::Turn(player) => {
+                let controller = get_current_controller(&session.game);
+                if controller != Controller::Human {
+                    play_engine_turn(player, &controller, &mut session);
+                    changed = true;
+                    continue;
+                }
+            },
+            GameState::GameWon(_) | GameState::Draw => {},
+        }
+
+        print!("> ");
+        std::io::stdout().flush().expect("Couldn't write to the terminal");
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break, // End of the input
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match parse_command(&line, &session.game) {
+            Ok(Command::Quit) => break,
+            Ok(command) => command,
+            Err(msg) => {
+                println!("ERR: {}", msg);
+                continue;
+            },
+        };
+
+        match run_command(command, &mut session) {
+            Ok(position_changed) => changed = position_changed,
+            Err(msg) => println!("ERR: {}", msg),
+        }
+    }
+}
+
+fn parse_command(line: &str, game: &rules::Game) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or("").to_lowercase();
+    let argument = words.next().map(|word| word.to_string());
+
+    match name.as_str() {
+        "undo" => return Ok(Command::Undo),
+        "redo" => return Ok(Command::Redo),
+        "hints" => return Ok(Command::Hints),
+        "swap" => return Ok(Command::Swap),
+        "solve" => return Ok(Command::Solve),
+        "new" => return Ok(Command::New),
+        "save" => return Ok(Command::Save(argument)),
+        "load" => return Ok(Command::Load(argument)),
+        "help" => return Ok(Command::Help),
+        "quit" | "exit" => return Ok(Command::Quit),
+        _ => {},
+    }
+
+    // Either only the position, or the full move when the player is written too
+    let text = line.trim();
+    let position = if text.contains(' ') {
+        let (player, position) = notation::parse_move(text)?;
+        match game.state {
+            GameState::Turn(current) if current != player => {
+                return Err(format!("It's not the turn of {}, but of {}", notation::PLAYER_LETTERS[player as usize], notation::PLAYER_LETTERS[current as usize]));
+            },
+            _ => {},
+        }
+        position
+    }
+    else {
+        notation::parse_pos(text)?
+    };
+
+    if !is_in_cube(&position, &game.board.config) {
+        return Err(format!("{} is outside of the cube", notation::pos_to_notation(&position)));
+    }
+
+    return Ok(Command::Play(position));
+}
+
+// Returns whether the position changed, so that the board is shown again
+fn run_command(command: Command, session: &mut Session) -> Result<bool, String> {
+    match command {
+        Command::Play(position) => {
+            let time = session.start_time.elapsed().as_secs_f32();
+            match rules::play_at(&position, time, &mut session.game) {
+                Some(played) => {
+                    println!("Played {}", notation::played_to_notation(&played));
+                    return Ok(true);
+                },
+                None => match session.game.state {
+                    GameState::Turn(_) => return Err(format!("{} can't be played, there is no room left", notation::pos_to_notation(&position))),
+                    GameState::GameWon(_) | GameState::Draw => return Err("The game is over, type new to play again".to_string()),
+                },
+            }
+        },
+        Command::Undo => {
+            // The moves of the computer are skipped, to get back to a human turn
+            let mut count = 0;
+            while count == 0 || get_current_controller(&session.game) != Controller::Human {
+                match rules::undo(&mut session.game) {
+                    Some(undone) => println!("Took back {}", notation::played_to_notation(&undone)),
+                    None => break,
+                }
+                count += 1;
+            }
+
+            if count == 0 {
+                return Err("There is no move to take back".to_string());
+            }
+            return Ok(true);
+        },
+        Command::Redo => {
+            let mut count = 0;
+            while count == 0 || get_current_controller(&session.game) != Controller::Human {
+                match rules::redo(&mut session.game) {
+                    Some(redone) => println!("Played again {}", notation::played_to_notation(&redone)),
+                    None => break,
+                }
+                count += 1;
+            }
+
+            if count == 0 {
+                return Err("There is no move to play again".to_string());
+            }
+            return Ok(true);
+        },
+        Command::Hints => {
+            let player = match session.game.state {
+                GameState::Turn(player) => player,
+                GameState::GameWon(_) | GameState::Draw => return Err("The game is over".to_string()),
+            };
+
+            let found = hints::find_hints(&session.game.board, player, &session.game.settings);
+            if found.is_empty() {
+                println!("Nothing urgent, any block will do");
+            }
+            for hint in found {
+                let reason = match hint.kind {
+                    hints::HintKind::Win => "wins",
+                    hints::HintKind::Block => "stops a win of the opponent",
+                    hints::HintKind::OpenFour => "leaves two ways to win",
+                };
+                println!("    {} {}", notation::pos_to_notation(&hint.position), reason);
+            }
+            return Ok(false);
+        },
+        Command::Swap => {
+            let time = session.start_time.elapsed().as_secs_f32();
+            match rules::swap_sides(time, &mut session.game) {
+                Some(played) => {
+                    println!("Swapped sides: {}", notation::played_to_notation(&played));
+                    return Ok(true);
+                },
+                None => return Err("Swapping is only allowed right after the first move, with --pie on".to_string()),
+            }
+        },
+        Command::Solve => {
+            let player = match session.game.state {
+                GameState::Turn(player) => player,
+                GameState::GameWon(_) | GameState::Draw => return Err("The game is over".to_string()),
+            };
+            if !solver::can_solve(&session.game) {
+                return Err(format!("Only cubes of up to {} blocks with 2 players can be solved, with the pie rule from the third move", solver::MAX_SOLVED_BLOCK_COUNT));
+            }
+
+            // Solved positions are shared with the window
+            let mut table = solver::load_solutions(solver::DEFAULT_SOLUTIONS_PATH)?;
+            let value = match solver::lookup(&session.game.board, player, &session.game.settings, &table) {
+                Some(value) => value,
+                None => {
+                    println!("Solving...");
+                    let cancelled = AtomicBool::new(false);
+                    let value = solver::solve(&session.game.board, player, &session.game.settings, solver::DEFAULT_NODE_LIMIT, &cancelled)
+                        .ok_or("The position has too many continuations to be solved".to_string())?;
+                    solver::insert(&session.game.board, player, value, &session.game.settings, &mut table);
+                    solver::save_solutions(solver::DEFAULT_SOLUTIONS_PATH, &table)?;
+                    value
+                },
+            };
+
+            println!("{}", solver::describe_value(&value, player, &session.game.settings));
+            return Ok(false);
+        },
+        Command::New => {
+            session.game = rules::new_game(session.game.settings.clone());
+            session.start_time = Instant::now();
+            println!("New game");
+            return Ok(true);
+        },
+        Command::Save(path) => {
+            let path = path.unwrap_or(session.save_path.clone());
+            record::write_record(&path, &record::create_record(&session.game, IDENTITY_ROTATION))?;
+            println!("Saved to {}", path);
+            return Ok(false);
+        },
+        Command::Load(path) => {
+            let path = path.unwrap_or(session.save_path.clone());
+            load_game(&path, session)?;
+            println!("Loaded {}", path);
+            return Ok(true);
+        },
+        Command::Help => {
+            println!("{}", HELP);
+            return Ok(false);
+        },
+        Command::Quit => return Ok(false),
+    }
+}
+
+// Who plays each symbol is not saved, the current players are kept
+fn load_game(path: &str, session: &mut Session) -> Result<(), String> {
+    let record = record::read_record(path)?;
+    session.game = record::record_to_game(&record, &session.game.settings)?;
+
+    // Keep timing the moves after the saved ones
+    let elapsed = match session.game.history.last() {
+        Some(last) => Duration::from_secs_f32(last.time.max(0.0)),
+        None => Duration::ZERO,
+    };
+    session.start_time = Instant::now().checked_sub(elapsed).unwrap_or(Instant::now());
+
+    return Ok(());
+}
+
+// The engine searches on this thread, nothing else happens in the meantime
+fn play_engine_turn(player: u8, controller: &Controller, session: &mut Session) {
+    let name = get_player_label(player, &session.game);
+    println!("{} is thinking...", name);
+
+    let cancelled = AtomicBool::new(false);
+    if rules::can_swap(&session.game) && engine::wants_swap(&session.game.board, player, controller, &session.game.settings, &cancelled) {
+        let time = session.start_time.elapsed().as_secs_f32();
+        match rules::swap_sides(time, &mut session.game) {
+            Some(played) => println!("{} swapped sides: {}", name, notation::played_to_notation(&played)),
+            None => {},
+        }
+        return;
+    }
+
+    let chosen = engine::run_engine(&session.game.board, player, controller, &session.game.settings, &cancelled);
+    let time = session.start_time.elapsed().as_secs_f32();
+    match chosen.and_then(|position| rules::play_at(&position, time, &mut session.game)) {
+        Some(played) => println!("{} played {}", name, notation::played_to_notation(&played)),
+        None => {
+            // Better than asking the engine again forever
+            println!("ERR: The engine of {} found no move, it is now played by hand", name);
+            session.game.settings.players[player as usize].controller = Controller::Human;
+        },
+    }
+}
+
+// Human when the game is over
+fn get_current_controller(game: &rules::Game) -> Controller {
+    match game.state {
+        GameState::Turn(player) => return game.settings.players[player as usize].controller.clone(),
+        GameState::GameWon(_) | GameState::Draw => return Controller::Human,
+    }
+}
+
+// Like "Cross (X)"
+fn get_player_label(player: u8, game: &rules::Game) -> String {
+    return format!("{} ({})", game.settings.players[player as usize].name, notation::PLAYER_LETTERS[player as usize]);
+}
+
+fn get_settings_summary(settings: &settings::GameSettings) -> String {
+    return format!("{} cube, wrap: {}, {} in a row to win, gravity: {}, pie rule: {}",
+        board_config_to_string(&settings.board),
+        wrap_to_string(&settings.board.wrap),
+        settings.count_to_win,
+        settings::placement_to_string(&settings.placement),
+        settings::switch_to_string(settings.pie_rule));
+}
+
+fn get_status(game: &rules::Game) -> String {
+    match &game.state {
+        GameState::Turn(player) => {
+            let computer = if game.settings.players[*player as usize].controller == Controller::Human { "" } else { " (computer)" };
+            let mut res = format!("Move {}, {}{} to play", game.history.len() + 1, get_player_label(*player, game), computer);
+            if rules::can_swap(game) {
+                res += ", or type swap to take the first symbol";
+            }
+            return res;
+        },
+        GameState::GameWon(lines) => {
+            let winner = match lines[0].winner {
+                BlockType::Player(id) => get_player_label(id, game),
+                BlockType::None => "Nobody".to_string(),
+            };
+
+            // The blocks of the lines are drawn in lowercase on the board
+            let mut res = format!("{} wins!", winner);
+            for info in lines {
+                let blocks: Vec<String> = rules::get_line_block_ids(info, &game.board, &game.settings).iter()
+                    .map(|id| notation::pos_to_notation(&id_to_pos(*id, &game.board.config)))
+                    .collect();
+                res += &format!("\n    Line: {}", blocks.join(" "));
+            }
+            return res;
+        },
+        GameState::Draw => {
+            if bitboard::is_full(&game.bits, &game.board.config) {
+                return "Draw! The cube is full".to_string();
+            }
+            return format!("Draw! Nobody can align {} symbols anymore", game.settings.count_to_win);
+        },
+    }
+}
+
+// The slices are drawn side by side, a few per row
+fn draw_board(game: &rules::Game) -> String {
+    let winning_ids: Vec<i32> = match &game.state {
+        GameState::GameWon(lines) => lines.iter().flat_map(|info| rules::get_line_block_ids(info, &game.board, &game.settings)).collect(),
+        GameState::Turn(_) | GameState::Draw => Vec::new(),
+    };
+
+    let slices: Vec<Vec<String>> = (0..game.board.config.size[2]).map(|z| draw_slice(z, &winning_ids, &game.board)).collect();
+
+    let mut rows = Vec::new();
+    for group in slices.chunks(SLICES_PER_ROW) {
+        for i in 0..group[0].len() {
+            let parts: Vec<&str> = group.iter().map(|slice| slice[i].as_str()).collect();
+            rows.push(parts.join(SLICE_GAP).trim_end().to_string());
+        }
+        rows.push(String::new());
+    }
+
+    rows.pop();
+    return rows.join("\n");
+}
+
+// Every line has the same width, y goes up like on the cube
+fn draw_slice(z: i32, winning_ids: &[i32], board: &Board) -> Vec<String> {
+    let size = board.config.size;
+    let width = 3 + 2 * size[0] as usize;
+
+    let mut res = Vec::new();
+    res.push(format!("{:<width$}", format!("   z = {}", z + 1)));
+    res.push(format!("{:<width$}", format!("   {}", (0..size[0]).map(|x| ((b'a' + x as u8) as char).to_string()).collect::<Vec<_>>().join(" "))));
+
+    for y in (0..size[1]).rev() {
+        let cells: Vec<String> = (0..size[0]).map(|x| {
+            let pos = vec3i(x, y, z);
+            match get_block(&pos, board) {
+                BlockType::Player(id) => {
+                    let letter = notation::PLAYER_LETTERS[id as usize];
+                    if winning_ids.contains(&pos_to_id(&pos, &board.config)) {
+                        return letter.to_ascii_lowercase().to_string();
+                    }
+                    return letter.to_string();
+                },
+                BlockType::None => return ".".to_string(),
+            }
+        }).collect();
+
+        res.push(format!("{:<width$}", format!("{:>2} {}", y + 1, cells.join(" "))));
+    }
+
+    return res;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_are_read_with_or_without_the_player() {
+        let game = rules::new_game(settings::default_settings());
+
+        match parse_command("c2.4", &game) {
+            Ok(Command::Play(pos)) => assert_eq!(pos, vec3i(2, 1, 3)),
+            _ => panic!("c2.4 should be a move"),
+        }
+        match parse_command("X c2.4", &game) {
+            Ok(Command::Play(pos)) => assert_eq!(pos, vec3i(2, 1, 3)),
+            _ => panic!("X c2.4 should be a move"),
+        }
+
+        assert!(parse_command("O c2.4", &game).is_err()); // Not the turn of O
+        assert!(parse_command("z1.1", &game).is_err()); // Outside of the cube
+        assert!(parse_command("c2", &game).is_err());
+    }
+
+    #[test]
+    fn every_slice_is_labelled() {
+        let mut game = rules::new_game(settings::default_settings());
+        rules::play_at(&vec3i(0, 0, 5), 0.0, &mut game);
+
+        let drawn = draw_board(&game);
+        for z in 1..=6 {
+            assert!(drawn.contains(&format!("z = {}", z)));
+        }
+
+        // Bottom row of the last slice
+        let bottom = drawn.lines().last().unwrap();
+        assert!(bottom.ends_with(" 1 X . . . . ."));
+    }
+}
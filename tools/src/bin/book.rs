@@ -0,0 +1,250 @@
+// Builds an opening book from earlier games: self-play datasets, or the games saved by a tournament.
+// The moves of the players that won get the most weight, the engines then play them more often.
+#![allow(clippy::needless_return, clippy::single_match)]
+
+extern crate csv;
+extern crate serde;
+extern crate serde_json;
+
+use game_core::board::*;
+use game_core::book;
+use game_core::notation;
+use game_core::protocol;
+use game_core::record;
+use game_core::rules::{BlockType, GameState};
+use game_core::settings::{self, GameSettings};
+use serde::Deserialize;
+use std::io::BufRead;
+
+const USAGE: &str = "\
+Usage: book --input <path> [options] [game options]
+    --input <path>        A self-play dataset (.csv or .jsonl), a saved game (.json) or a folder of saved games,
+                          like the one written by tournament --save-games, can be repeated
+    --plies <n>           Moves learnt from the start of every game (default: 8)
+    --output <path>       File to write (default: ./book.json)
+    Game options like --size, --win, --wrap and --gravity set the rules, games with other rules are skipped";
+
+const DEFAULT_PLIES: usize = 8;
+
+struct BookOptions {
+    inputs: Vec<String>,
+    plies: usize,
+    output_path: String,
+    settings: GameSettings,
+}
+
+// The columns of the self-play datasets that the book needs
+#[derive(Deserialize)]
+struct DatasetRow {
+    ply: usize,
+    size: String,
+    wrap: String,
+    win: i32,
+    gravity: String,
+    board: String,
+    to_move: char,
+    chosen: String,
+    result: i8,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(msg) => {
+            println!("ERR: {}", msg);
+            println!("{}", USAGE);
+            std::process::exit(1);
+        },
+    };
+
+    let mut opening_book = book::new_book();
+    for path in &options.inputs {
+        match read_input(path, &options, &mut opening_book) {
+            Ok(count) => println!("LOG: Learnt {} moves from {}", count, path),
+            Err(msg) => {
+                println!("ERR: {}", msg);
+                std::process::exit(1);
+            },
+        }
+    }
+
+    match book::save_book(&options.output_path, &opening_book) {
+        Ok(()) => println!("LOG: Wrote {} positions to {}", opening_book.positions.len(), options.output_path),
+        Err(msg) => {
+            println!("ERR: {}", msg);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<BookOptions, String> {
+    let mut inputs = Vec::new();
+    let mut plies = DEFAULT_PLIES;
+    let mut output_path = book::DEFAULT_BOOK_PATH.to_string();
+    let mut game_args = vec!["--book".to_string(), "none".to_string()]; // Not the book being replaced
+
+    let mut i = 0;
+    while i < args.len() {
+        let name = args[i].as_str();
+        let value = args.get(i + 1).ok_or(format!("Missing value after '{}'", name));
+
+        match name {
+            "--input" => inputs.push(value?.clone()),
+            "--plies" => plies = parse_number(name, value?)?,
+            "--output" => output_path = value?.clone(),
+            _ => {
+                game_args.push(args[i].clone());
+                game_args.push(value?.clone());
+            },
+        }
+
+        i += 2;
+    }
+
+    if inputs.is_empty() {
+        return Err("Nothing to learn from, give at least one --input".to_string());
+    }
+
+    let settings = settings::parse_args(&game_args)?.settings;
+    return Ok(BookOptions { inputs, plies, output_path, settings });
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    return value.parse::<usize>().map_err(|_| format!("'{}' is not a valid number for {}", value, name));
+}
+
+// Returns how many moves were added to the book
+fn read_input(path: &str, options: &BookOptions, opening_book: &mut book::OpeningBook) -> Result<usize, String> {
+    if std::path::Path::new(path).is_dir() {
+        let entries = std::fs::read_dir(path).map_err(|err| format!("Couldn't read '{}': {}", path, err))?;
+        let mut files: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().to_string_lossy().to_string())
+            .filter(|file| file.ends_with(".json"))
+            .collect();
+        files.sort();
+
+        let mut count = 0;
+        for file in files {
+            count += read_saved_game(&file, options, opening_book)?;
+        }
+        return Ok(count);
+    }
+
+    if path.ends_with(".csv") {
+        let mut reader = csv::Reader::from_path(path).map_err(|err| format!("Couldn't read '{}': {}", path, err))?;
+        let mut count = 0;
+        for row in reader.deserialize::<DatasetRow>() {
+            let row = row.map_err(|err| format!("Invalid dataset '{}': {}", path, err))?;
+            count += learn_row(&row, options, opening_book)?;
+        }
+        return Ok(count);
+    }
+
+    if path.ends_with(".jsonl") {
+        let file = std::fs::File::open(path).map_err(|err| format!("Couldn't read '{}': {}", path, err))?;
+        let mut count = 0;
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line.map_err(|err| format!("Couldn't read '{}': {}", path, err))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row: DatasetRow = serde_json::from_str(&line).map_err(|err| format!("Invalid dataset '{}': {}", path, err))?;
+            count += learn_row(&row, options, opening_book)?;
+        }
+        return Ok(count);
+    }
+
+    return read_saved_game(path, options, opening_book);
+}
+
+// Unfinished games, like forfeits, and games with other rules teach nothing
+fn read_saved_game(path: &str, options: &BookOptions, opening_book: &mut book::OpeningBook) -> Result<usize, String> {
+    let game_record = record::read_record(path)?;
+    let game = record::record_to_game(&game_record, &options.settings).map_err(|msg| format!("{}: {}", path, msg))?;
+    if protocol::format_rules(&game.settings) != protocol::format_rules(&options.settings) {
+        return Ok(0);
+    }
+
+    let winner = match &game.state {
+        GameState::GameWon(lines) => match lines[0].winner {
+            BlockType::Player(id) => Some(id),
+            BlockType::None => None,
+        },
+        GameState::Draw => None,
+        GameState::Turn(_) => return Ok(0),
+    };
+
+    book::add_game(&game.history, winner, options.plies, &options.settings, opening_book);
+    let learnt = game.history.iter().take(options.plies).filter(|played| winner.is_none() || winner == Some(played.player)).count();
+    return Ok(learnt);
+}
+
+// One position of a dataset, its move is learnt if its player didn't lose
+fn learn_row(row: &DatasetRow, options: &BookOptions, opening_book: &mut book::OpeningBook) -> Result<usize, String> {
+    let settings = &options.settings;
+    let same_rules = row.size == board_config_to_string(&settings.board)
+        && row.wrap == wrap_to_string(&settings.board.wrap)
+        && row.win == settings.count_to_win
+        && row.gravity == settings::placement_to_string(&settings.placement);
+    if !same_rules || row.ply >= options.plies {
+        return Ok(0);
+    }
+
+    let weight = match row.result {
+        1 => book::WIN_WEIGHT,
+        0 => book::DRAW_WEIGHT,
+        _ => return Ok(0),
+    };
+
+    let player = notation::parse_player(&row.to_move.to_string())?;
+    let mut board = new_board(&settings.board);
+    if row.board.len() != board.blocks.len() {
+        return Err(format!("The board '{}' doesn't fit a {} cube", row.board, row.size));
+    }
+    for (id, letter) in row.board.chars().enumerate() {
+        if letter != '.' {
+            board.blocks[id] = BlockType::Player(notation::parse_player(&letter.to_string())?);
+        }
+    }
+
+    let pos = notation::parse_pos(&row.chosen)?;
+    if !is_in_cube(&pos, &board.config) {
+        return Err(format!("{} is not in the cube", row.chosen));
+    }
+
+    book::add_move(&board, player, &pos, weight, settings, opening_book);
+    return Ok(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn losing_moves_and_other_rules_are_skipped() {
+        let options = parse_options(&["--input".to_string(), "games".to_string(), "--size".to_string(), "3".to_string()]).unwrap();
+        let mut opening_book = book::new_book();
+        let row = |board: &str, to_move: char, chosen: &str, result: i8, size: &str| DatasetRow {
+            ply: board.chars().filter(|letter| *letter != '.').count(),
+            size: size.to_string(),
+            wrap: "torus".to_string(),
+            win: 3,
+            gravity: "none".to_string(),
+            board: board.to_string(),
+            to_move,
+            chosen: chosen.to_string(),
+            result,
+        };
+
+        let empty = ".".repeat(27);
+        assert_eq!(learn_row(&row(&empty, 'X', "b2.2", 1, "3x3x3"), &options, &mut opening_book), Ok(1));
+        assert_eq!(learn_row(&row(&empty, 'X', "a1.1", -1, "3x3x3"), &options, &mut opening_book), Ok(0));
+        assert_eq!(learn_row(&row(&".".repeat(64), 'X', "a1.1", 1, "4x4x4"), &options, &mut opening_book), Ok(0));
+
+        let moves = book::get_book_moves(&new_board(&options.settings.board), 0, &options.settings, &opening_book);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].1, book::WIN_WEIGHT);
+    }
+}
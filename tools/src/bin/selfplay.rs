@@ -0,0 +1,285 @@
+// Plays the alpha-beta engine against itself and exports every position it played from,
+// with its evaluation and the final result of the game, for offline analysis
+#![allow(clippy::needless_return, clippy::single_match)]
+
+extern crate csv;
+extern crate serde;
+extern crate serde_json;
+
+use game_core::ai;
+use game_core::arena;
+use game_core::board::*;
+use game_core::notation;
+use game_core::rng;
+use game_core::rules::{self, BlockType, GameState};
+use game_core::settings::{self, Controller, GameSettings};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+
+const USAGE: &str = "\
+Usage: selfplay [options] [game options]
+    --games <n>           Number of games (default: 100)
+    --engine <level>      Alpha-beta engine playing both sides, like easy, 3 or hard:sym (default: medium)
+    --seed <n>            Seed of the first game, the next games use the following numbers (default: 1)
+    --random-moves <n>    Random moves at the start of every game, they are not exported (default: 2)
+    --format <format>     csv or jsonl, one JSON object per line (default: csv)
+    --output <path>       File to write (default: ./selfplay.csv or ./selfplay.jsonl)
+    Game options like --size, --win, --wrap, --gravity and --players set the rules,
+    --book <path> gives an opening book to the engine (default: none)";
+
+const DEFAULT_GAME_COUNT: usize = 100;
+const DEFAULT_SEED: u64 = 1;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Csv, JsonLines,
+}
+
+enum DatasetWriter {
+    Csv(Box<csv::Writer<std::fs::File>>), // Writes the header line before the first record
+    JsonLines(std::io::BufWriter<std::fs::File>),
+}
+
+struct ExportOptions {
+    game_count: usize,
+    engine: ai::AlphaBetaConfig,
+    seed: u64,
+    random_moves: usize,
+    format: Format,
+    output_path: String,
+    settings: GameSettings,
+}
+
+// One position, before the engine's move
+#[derive(Serialize)]
+struct PositionRecord {
+    game: usize,
+    ply: usize, // Moves played before this position, random ones included
+    size: String, // Like 6x6x6
+    wrap: String,
+    win: i32, // Symbols to align
+    gravity: String, // Like -y, or none
+    board: String, // One letter per block, by increasing block id, '.' for empty blocks
+    to_move: char,
+    chosen: String, // The move of the engine, like c2.4
+    evaluation: i64, // Score of the search for the player to move, above 1e12 for a forced win
+    result: i8, // 1 if the player to move won the game in the end, -1 if it lost, 0 for a draw
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(msg) => {
+            println!("ERR: {}", msg);
+            println!("{}", USAGE);
+            std::process::exit(1);
+        },
+    };
+
+    match export_games(&options) {
+        Ok(count) => println!("LOG: Wrote {} positions of {} games to {}", count, options.game_count, options.output_path),
+        Err(msg) => {
+            println!("ERR: {}", msg);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<ExportOptions, String> {
+    let mut game_count = DEFAULT_GAME_COUNT;
+    let mut engine = ai::default_alpha_beta_config();
+    let mut seed = DEFAULT_SEED;
+    let mut random_moves = arena::DEFAULT_RANDOM_MOVES;
+    let mut format = Format::Csv;
+    let mut output_path = None;
+    let mut game_args = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let name = args[i].as_str();
+        let value = args.get(i + 1).ok_or(format!("Missing value after '{}'", name));
+
+        match name {
+            "--games" => game_count = parse_number(name, value?)? as usize,
+            "--engine" => {
+                engine = match settings::parse_controller(value?)? {
+                    Controller::AlphaBeta(config) => config,
+                    _ => return Err("Only the alpha-beta engine gives evaluations, expected a level like medium or 3".to_string()),
+                };
+            },
+            "--seed" => seed = parse_number(name, value?)?,
+            "--random-moves" => random_moves = parse_number(name, value?)? as usize,
+            "--format" => {
+                format = match value?.as_str() {
+                    "csv" => Format::Csv,
+                    "jsonl" => Format::JsonLines,
+                    other => return Err(format!("Unknown format '{}', expected csv or jsonl", other)),
+                };
+            },
+            "--output" => output_path = Some(value?.clone()),
+            _ => {
+                game_args.push(args[i].clone());
+                game_args.push(value?.clone());
+            },
+        }
+
+        i += 2;
+    }
+
+    let output_path = match output_path {
+        Some(path) => path,
+        None => if format == Format::Csv { "./selfplay.csv".to_string() } else { "./selfplay.jsonl".to_string() },
+    };
+
+    let settings = settings::parse_args(&game_args)?.settings;
+    return Ok(ExportOptions { game_count, engine, seed, random_moves, format, output_path, settings });
+}
+
+fn parse_number(name: &str, value: &str) -> Result<u64, String> {
+    return value.parse::<u64>().map_err(|_| format!("'{}' is not a valid number for {}", value, name));
+}
+
+// Writes the positions game after game, returns how many were written
+fn export_games(options: &ExportOptions) -> Result<usize, String> {
+    let file = std::fs::File::create(&options.output_path).map_err(|err| format!("Couldn't create '{}': {}", options.output_path, err))?;
+    let mut writer = match options.format {
+        Format::Csv => DatasetWriter::Csv(Box::new(csv::Writer::from_writer(file))),
+        Format::JsonLines => DatasetWriter::JsonLines(std::io::BufWriter::new(file)),
+    };
+
+    let mut count = 0;
+    for game_index in 0..options.game_count {
+        let records = play_game(game_index, options.seed + game_index as u64, options);
+        count += records.len();
+
+        for record in records {
+            write_record(&record, &mut writer).map_err(|err| format!("Couldn't write '{}': {}", options.output_path, err))?;
+        }
+
+        println!("LOG: Game {}/{} done", game_index + 1, options.game_count);
+    }
+
+    let flushed = match &mut writer {
+        DatasetWriter::Csv(writer) => writer.flush(),
+        DatasetWriter::JsonLines(writer) => writer.flush(),
+    };
+    flushed.map_err(|err| format!("Couldn't write '{}': {}", options.output_path, err))?;
+
+    return Ok(count);
+}
+
+fn write_record(record: &PositionRecord, writer: &mut DatasetWriter) -> Result<(), String> {
+    match writer {
+        DatasetWriter::Csv(writer) => return writer.serialize(record).map_err(|err| err.to_string()),
+        DatasetWriter::JsonLines(writer) => {
+            let line = serde_json::to_string(record).map_err(|err| err.to_string())?;
+            return writeln!(writer, "{}", line).map_err(|err| err.to_string());
+        },
+    }
+}
+
+// The results are only known at the end, so the positions of a game are kept until then
+fn play_game(game_index: usize, seed: u64, options: &ExportOptions) -> Vec<PositionRecord> {
+    let mut game = rules::new_game(options.settings.clone());
+    let mut rng = rng::new_rng(seed);
+    let cancelled = AtomicBool::new(false);
+    let mut records = Vec::new();
+
+    while let GameState::Turn(player) = game.state {
+        let ply = game.history.len();
+
+        // The swap of the pie rule is not a position of the dataset, the next ones are
+        if rules::can_swap(&game) && ai::wants_swap(&game.board, player, &options.engine, &game.settings, &cancelled) {
+            rules::swap_sides(ply as f32, &mut game);
+            continue;
+        }
+
+        let chosen = if ply < options.random_moves {
+            arena::get_random_move(&game, &mut rng)
+        }
+        else {
+            match ai::analyse_position(&game.board, player, &options.engine, &game.settings, &cancelled) {
+                Some((pos, evaluation)) => {
+                    records.push(PositionRecord {
+                        game: game_index,
+                        ply,
+                        size: board_config_to_string(&game.board.config),
+                        wrap: wrap_to_string(&game.board.config.wrap),
+                        win: game.settings.count_to_win,
+                        gravity: settings::placement_to_string(&game.settings.placement),
+                        board: board_to_string(&game.board),
+                        to_move: notation::PLAYER_LETTERS[player as usize],
+                        chosen: notation::pos_to_notation(&pos),
+                        evaluation,
+                        result: 0,
+                    });
+                    Some(pos)
+                },
+                None => None,
+            }
+        };
+
+        match chosen.and_then(|pos| rules::play_at(&pos, ply as f32, &mut game)) {
+            Some(_) => {},
+            None => break, // Can't happen while the game goes on, but never loop forever
+        }
+    }
+
+    let winner = match &game.state {
+        GameState::GameWon(lines) => lines[0].winner,
+        GameState::Turn(_) | GameState::Draw => BlockType::None,
+    };
+    for record in records.iter_mut() {
+        record.result = match winner {
+            BlockType::Player(id) if notation::PLAYER_LETTERS[id as usize] == record.to_move => 1,
+            BlockType::Player(_) => -1,
+            BlockType::None => 0,
+        };
+    }
+
+    return records;
+}
+
+fn board_to_string(board: &Board) -> String {
+    return board.blocks.iter().map(|block| match block {
+        BlockType::Player(id) => notation::PLAYER_LETTERS[*id as usize],
+        BlockType::None => '.',
+    }).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_follow_the_winner() {
+        let options = ExportOptions {
+            game_count: 1,
+            engine: ai::parse_alpha_beta_config("2").unwrap(),
+            seed: 3,
+            random_moves: 2,
+            format: Format::Csv,
+            output_path: String::new(),
+            settings: settings::parse_args(&["--size".to_string(), "3".to_string(), "--wrap".to_string(), "bounded".to_string()]).unwrap().settings,
+        };
+
+        let records = play_game(0, options.seed, &options);
+        assert!(!records.is_empty());
+        assert_eq!(records[0].ply, 2);
+        assert_eq!(records[0].board.len(), 27);
+        assert_eq!(records[0].board.chars().filter(|block| *block != '.').count(), 2);
+
+        // Both players can't have won
+        let last = records.last().unwrap();
+        for record in &records {
+            if last.result != 0 {
+                assert_eq!(record.result, if record.to_move == last.to_move { last.result } else { -last.result });
+            }
+            else {
+                assert_eq!(record.result, 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// Solves the positions of a small cube and adds them to the solutions file that the window
+// and the terminal read, so that they know who wins without searching again
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use game_core::board::id_to_pos;
+use game_core::notation;
+use game_core::rules::{self, GameState};
+use game_core::settings::{self, GameSettings};
+use game_core::solver;
+use game_core::zobrist;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
+
+const USAGE: &str = "\
+Usage: solver [options] [game options]
+    --moves <list>        Moves played before the position to solve, like \"b2.2 a1.1\" (default: none)
+    --depth <n>           Also solve every position up to n moves later, symmetric ones once (default: 0)
+    --nodes <n>           Largest search tree, in positions (default: 2000000)
+    --output <path>       Solutions file, completed if it already exists (default: ./solutions.json)
+    Game options like --size, --win, --wrap and --gravity set the rules, with 2 players and at most 64 blocks
+    (with --pie on, --moves must go past the second move)";
+
+struct SolverOptions {
+    moves: Vec<String>,
+    depth: usize,
+    node_limit: usize,
+    output_path: String,
+    settings: GameSettings,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(msg) => {
+            println!("ERR: {}", msg);
+            println!("{}", USAGE);
+            std::process::exit(1);
+        },
+    };
+
+    match run_solver(&options) {
+        Ok(count) => println!("LOG: {} positions solved, saved to {}", count, options.output_path),
+        Err(msg) => {
+            println!("ERR: {}", msg);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<SolverOptions, String> {
+    let mut moves = Vec::new();
+    let mut depth = 0;
+    let mut node_limit = solver::DEFAULT_NODE_LIMIT;
+    let mut output_path = solver::DEFAULT_SOLUTIONS_PATH.to_string();
+    let mut game_args = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let name = args[i].as_str();
+        let value = args.get(i + 1).ok_or(format!("Missing value after '{}'", name));
+
+        match name {
+            "--moves" => moves = value?.split_whitespace().map(|text| text.to_string()).collect(),
+            "--depth" => depth = parse_number(name, value?)?,
+            "--nodes" => node_limit = parse_number(name, value?)?,
+            "--output" => output_path = value?.clone(),
+            _ => {
+                game_args.push(args[i].clone());
+                game_args.push(value?.clone());
+            },
+        }
+
+        i += 2;
+    }
+
+    let settings = settings::parse_args(&game_args)?.settings;
+    return Ok(SolverOptions { moves, depth, node_limit, output_path, settings });
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    return value.parse::<usize>().map_err(|_| format!("'{}' is not a valid number for {}", value, name));
+}
+
+// Returns how many positions were solved, the ones already in the file don't count
+fn run_solver(options: &SolverOptions) -> Result<usize, String> {
+    let mut game = rules::new_game(options.settings.clone());
+    for text in &options.moves {
+        let pos = notation::parse_pos(text)?;
+        rules::play_at(&pos, 0.0, &mut game).ok_or(format!("{} can't be played", text))?;
+    }
+    if !solver::can_solve(&game) {
+        return Err(format!("Only cubes of up to {} blocks with 2 players can be solved, with the pie rule from the third move", solver::MAX_SOLVED_BLOCK_COUNT));
+    }
+
+    let mut table = solver::load_solutions(&options.output_path)?;
+    let mut seen = HashSet::new();
+    let count = solve_positions(&mut game, options.depth, options, &mut seen, &mut table);
+
+    solver::save_solutions(&options.output_path, &table)?;
+    return Ok(count);
+}
+
+// Solves the position of `game`, then the ones after each of its moves while `depth` allows it
+fn solve_positions(game: &mut rules::Game, depth: usize, options: &SolverOptions, seen: &mut HashSet<String>, table: &mut solver::SolutionTable) -> usize {
+    let player = match game.state {
+        GameState::Turn(player) => player,
+        GameState::GameWon(_) | GameState::Draw => return 0,
+    };
+    if !seen.insert(zobrist::get_position_key(&game.board, player, &game.settings).0) {
+        return 0;
+    }
+
+    let moves: Vec<String> = game.history.iter().map(|played| notation::pos_to_notation(&played.position)).collect();
+    let label = if moves.is_empty() { "start".to_string() } else { moves.join(" ") };
+
+    let mut count = 0;
+    match solver::lookup(&game.board, player, &game.settings, table) {
+        Some(value) => println!("LOG: {}: {} (already known)", label, solver::describe_value(&value, player, &game.settings)),
+        None => {
+            match solver::solve(&game.board, player, &game.settings, options.node_limit, &AtomicBool::new(false)) {
+                Some(value) => {
+                    println!("LOG: {}: {}", label, solver::describe_value(&value, player, &game.settings));
+                    solver::insert(&game.board, player, value, &game.settings, table);
+                    count += 1;
+                },
+                None => println!("LOG: {}: too many continuations, try a larger --nodes", label),
+            }
+        },
+    }
+
+    if depth == 0 {
+        return count;
+    }
+
+    for id in 0..game.board.blocks.len() as i32 {
+        let pos = id_to_pos(id, &game.board.config);
+        if rules::get_landing_pos(&pos, &game.board, &game.settings) != Some(pos.clone()) {
+            continue;
+        }
+
+        rules::play_at(&pos, 0.0, game);
+        count += solve_positions(game, depth - 1, options, seen, table);
+        rules::undo(game);
+    }
+
+    return count;
+}
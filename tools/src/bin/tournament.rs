@@ -0,0 +1,311 @@
+// Plays every pair of engines against each other and prints a results table,
+// to check whether a change to an engine actually makes it stronger
+#![allow(clippy::needless_return, clippy::single_match)]
+
+use game_core::arena;
+use game_core::notation;
+use game_core::protocol;
+use game_core::record;
+use game_core::settings::{self, Controller, GameSettings};
+
+const USAGE: &str = "\
+Usage: tournament --engine <level> --engine <level> [options] [game options]
+    --engine <level>      An engine taking part, like medium, 4:sym, mcts:500 or engine:./dummy-engine, at least two
+    --games <n>           Games played by each pair of engines, each one starts half of them (default: 10)
+    --seed <n>            Seed of the first game, the next pairs of games use the following numbers (default: 1)
+    --random-moves <n>    Random moves at the start of every game, so that the games differ (default: 2)
+    --movetime <ms>       Milliseconds that the engine: programs get for each move (default: 5000)
+    --output <path>       Also write the results table to this file
+    --save-games <folder> Also save every game in this folder, to build an opening book from them
+    Game options like --size, --win, --wrap and --gravity set the rules, with 2 players,
+    --book <path> gives an opening book to the engines (default: none)";
+
+const DEFAULT_GAME_COUNT: usize = 10;
+const DEFAULT_SEED: u64 = 1;
+const ELO_ITERATIONS: usize = 2000;
+const IDENTITY_ROTATION: [f32; 4] = [0.0, 0.0, 0.0, 1.0]; // Saved as the rotation of the cube, there is none here
+
+struct TournamentOptions {
+    engines: Vec<(String, Controller)>, // Name as written on the command line, and the engine
+    game_count: usize,
+    seed: u64,
+    random_moves: usize,
+    output_path: Option<String>,
+    games_path: Option<String>,
+    settings: GameSettings,
+}
+
+// One game, with the engines as indices in the options
+struct Outcome {
+    first: usize, // Plays X
+    second: usize,
+    winner: Option<usize>, // None for a draw
+    length: usize, // Number of moves
+}
+
+#[derive(Default)]
+struct EngineStats {
+    games: usize,
+    wins: usize,
+    draws: usize,
+    losses: usize,
+    moves: usize, // Total length of its games
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(msg) => {
+            println!("ERR: {}", msg);
+            println!("{}", USAGE);
+            std::process::exit(1);
+        },
+    };
+
+    let outcomes = run_tournament(&options);
+    let table = get_results_table(&options, &outcomes);
+    println!();
+    println!("{}", table);
+
+    match &options.output_path {
+        Some(path) => {
+            match std::fs::write(path, &table) {
+                Ok(()) => println!("LOG: Results written to {}", path),
+                Err(err) => println!("ERR: Couldn't write '{}': {}", path, err),
+            }
+        },
+        None => {},
+    }
+}
+
+// The options of the tournament are read here, the other ones are given to the game settings
+fn parse_options(args: &[String]) -> Result<TournamentOptions, String> {
+    let mut engines = Vec::new();
+    let mut game_count = DEFAULT_GAME_COUNT;
+    let mut seed = DEFAULT_SEED;
+    let mut random_moves = arena::DEFAULT_RANDOM_MOVES;
+    let mut output_path = None;
+    let mut games_path = None;
+    let mut move_time_ms = None;
+    let mut game_args = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let name = args[i].as_str();
+        let value = args.get(i + 1).ok_or(format!("Missing value after '{}'", name));
+
+        match name {
+            "--engine" => {
+                let level = value?;
+                engines.push((level.clone(), settings::parse_controller(level)?));
+            },
+            "--games" => game_count = parse_number(name, value?)? as usize,
+            "--seed" => seed = parse_number(name, value?)?,
+            "--random-moves" => random_moves = parse_number(name, value?)? as usize,
+            "--output" => output_path = Some(value?.clone()),
+            "--save-games" => games_path = Some(value?.clone()),
+            "--movetime" => move_time_ms = Some(protocol::parse_move_time(value?)?),
+            _ => {
+                game_args.push(args[i].clone());
+                game_args.push(value?.clone());
+            },
+        }
+
+        i += 2;
+    }
+
+    match move_time_ms {
+        Some(move_time_ms) => {
+            for (_, controller) in engines.iter_mut() {
+                settings::set_move_time(controller, move_time_ms);
+            }
+        },
+        None => {},
+    }
+
+    if engines.len() < 2 {
+        return Err("A tournament needs at least two engines".to_string());
+    }
+    if game_count == 0 {
+        return Err("Each pair of engines must play at least one game".to_string());
+    }
+
+    let settings = settings::parse_args(&game_args)?.settings;
+    if settings.players.len() != 2 {
+        return Err("Tournament games are played by 2 players".to_string());
+    }
+
+    match &games_path {
+        Some(path) => std::fs::create_dir_all(path).map_err(|err| format!("Couldn't create '{}': {}", path, err))?,
+        None => {},
+    }
+
+    return Ok(TournamentOptions { engines, game_count, seed, random_moves, output_path, games_path, settings });
+}
+
+fn parse_number(name: &str, value: &str) -> Result<u64, String> {
+    return value.parse::<u64>().map_err(|_| format!("'{}' is not a valid number for {}", value, name));
+}
+
+// Every pair plays its games one after the other. The two games of each seed have the same
+// random opening, with the engines swapped, so that neither gets the better start.
+fn run_tournament(options: &TournamentOptions) -> Vec<Outcome> {
+    let pair_count = options.engines.len() * (options.engines.len() - 1) / 2;
+    let total = pair_count * options.game_count;
+    let mut outcomes = Vec::with_capacity(total);
+
+    for a in 0..options.engines.len() {
+        for b in (a + 1)..options.engines.len() {
+            for k in 0..options.game_count {
+                let (first, second) = if k % 2 == 0 { (a, b) } else { (b, a) };
+                let seed = options.seed + (k / 2) as u64;
+
+                let controllers = vec![options.engines[first].1.clone(), options.engines[second].1.clone()];
+                let played = arena::play_game(&options.settings, &controllers, options.random_moves, seed);
+
+                let winner = match arena::get_winner(&played) {
+                    Some(0) => Some(first),
+                    Some(_) => Some(second),
+                    None => None,
+                };
+                let outcome = Outcome { first, second, winner, length: played.game.history.len() };
+
+                let result = match (winner, played.forfeit) {
+                    (_, Some(player)) => format!("{} forfeits", notation::PLAYER_LETTERS[player as usize]),
+                    (Some(engine), None) => format!("{} wins", notation::PLAYER_LETTERS[if engine == first { 0 } else { 1 }]),
+                    (None, None) => "draw".to_string(),
+                };
+                println!("LOG: Game {}/{} (seed {}): {} (X) vs {} (O), {} after {} moves",
+                    outcomes.len() + 1, total, seed, options.engines[first].0, options.engines[second].0, result, outcome.length);
+
+                match &options.games_path {
+                    Some(folder) => {
+                        let path = format!("{}/game-{:04}.json", folder, outcomes.len() + 1);
+                        match record::write_record(&path, &record::create_record(&played.game, IDENTITY_ROTATION)) {
+                            Ok(()) => {},
+                            Err(msg) => println!("ERR: {}", msg),
+                        }
+                    },
+                    None => {},
+                }
+
+                outcomes.push(outcome);
+            }
+        }
+    }
+
+    return outcomes;
+}
+
+fn get_engine_stats(engine_count: usize, outcomes: &[Outcome]) -> Vec<EngineStats> {
+    let mut stats: Vec<EngineStats> = (0..engine_count).map(|_| EngineStats::default()).collect();
+
+    for outcome in outcomes {
+        for engine in [outcome.first, outcome.second] {
+            let engine_stats = &mut stats[engine];
+            engine_stats.games += 1;
+            engine_stats.moves += outcome.length;
+            match outcome.winner {
+                Some(winner) if winner == engine => engine_stats.wins += 1,
+                Some(_) => engine_stats.losses += 1,
+                None => engine_stats.draws += 1,
+            }
+        }
+    }
+
+    return stats;
+}
+
+// Ratings that best explain the results, with an average of 0. Every engine also gets a virtual draw
+// against an average engine, so that winning every game doesn't push its rating to infinity.
+fn estimate_elo(engine_count: usize, outcomes: &[Outcome]) -> Vec<f64> {
+    let expected_score = |rating: f64, opponent: f64| 1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0));
+    let mut ratings = vec![0.0; engine_count];
+
+    for _ in 0..ELO_ITERATIONS {
+        let mut next = ratings.clone();
+        for engine in 0..engine_count {
+            let mut score = 0.5;
+            let mut expected = expected_score(ratings[engine], 0.0);
+            let mut games = 1.0;
+
+            for outcome in outcomes {
+                let opponent = if outcome.first == engine { outcome.second } else if outcome.second == engine { outcome.first } else { continue };
+                score += match outcome.winner {
+                    Some(winner) if winner == engine => 1.0,
+                    Some(_) => 0.0,
+                    None => 0.5,
+                };
+                expected += expected_score(ratings[engine], ratings[opponent]);
+                games += 1.0;
+            }
+
+            // Small steps, the sum of the updates converges without overshooting
+            next[engine] += 400.0 * (score - expected) / games;
+        }
+
+        let average = next.iter().sum::<f64>() / engine_count as f64;
+        ratings = next.iter().map(|rating| rating - average).collect();
+    }
+
+    return ratings;
+}
+
+fn get_results_table(options: &TournamentOptions, outcomes: &[Outcome]) -> String {
+    let stats = get_engine_stats(options.engines.len(), outcomes);
+    let elo = estimate_elo(options.engines.len(), outcomes);
+    let name_width = options.engines.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max("Engine".len());
+
+    let mut res = format!("{:<name_width$}  Games  Wins  Draws  Losses   Score  Avg length    Elo\n", "Engine");
+    for (engine, (name, _)) in options.engines.iter().enumerate() {
+        let engine_stats = &stats[engine];
+        let games = engine_stats.games.max(1) as f64;
+        let score = (engine_stats.wins as f64 + 0.5 * engine_stats.draws as f64) / games;
+        let rating = elo[engine].round() + 0.0; // Adding zero turns -0 into 0
+
+        res += &format!("{:<name_width$}  {:>5}  {:>4}  {:>5}  {:>6}  {:>5.1}%  {:>10.1}  {:>+5.0}\n",
+            name,
+            engine_stats.games,
+            engine_stats.wins,
+            engine_stats.draws,
+            engine_stats.losses,
+            100.0 * score,
+            engine_stats.moves as f64 / games,
+            rating);
+    }
+
+    res += &format!("\n{} games, {} random moves at the start, first seed {}", outcomes.len(), options.random_moves, options.seed);
+    return res;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(first: usize, second: usize, winner: Option<usize>) -> Outcome {
+        return Outcome { first, second, winner, length: 10 };
+    }
+
+    #[test]
+    fn stronger_engine_gets_higher_elo() {
+        // 3 wins out of 4 games, a little less than the 191 points of a 75% score because of the virtual draws
+        let outcomes = vec![outcome(0, 1, Some(0)), outcome(1, 0, Some(0)), outcome(0, 1, Some(0)), outcome(1, 0, Some(1))];
+        let elo = estimate_elo(2, &outcomes);
+
+        assert!(elo[0] > 0.0 && elo[1] < 0.0);
+        assert!((elo[0] + elo[1]).abs() < 1e-6);
+        assert!(elo[0] - elo[1] > 100.0 && elo[0] - elo[1] < 191.0);
+    }
+
+    #[test]
+    fn equal_results_give_equal_elo() {
+        let outcomes = vec![outcome(0, 1, Some(0)), outcome(1, 0, Some(1)), outcome(0, 1, None), outcome(1, 0, None)];
+        let elo = estimate_elo(2, &outcomes);
+        assert!(elo[0].abs() < 1e-6 && elo[1].abs() < 1e-6);
+
+        let stats = get_engine_stats(2, &outcomes);
+        assert_eq!((stats[0].wins, stats[0].draws, stats[0].losses), (1, 2, 1));
+        assert_eq!((stats[1].wins, stats[1].draws, stats[1].losses), (1, 2, 1));
+    }
+}